@@ -1,4 +1,9 @@
-use anyhow::Result;
+use std::{
+    fmt::Display,
+    ops::{BitAnd, BitOr, Sub},
+};
+
+use anyhow::{bail, Context, Result};
 
 fn item_value(item: char) -> i32 {
     // Convert to an uppercase char here so that a and A = 1
@@ -12,36 +17,277 @@ fn item_value(item: char) -> i32 {
     c_value
 }
 
+fn value_item(value: i32) -> char {
+    match value {
+        1..=26 => (b'a' + (value - 1) as u8) as char,
+        _ => (b'A' + (value - 27) as u8) as char,
+    }
+}
+
+/// A set of rucksack items stored as a bitmask, where bit `n - 1` is set if the item with
+/// priority `n` is present.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ItemSet(u64);
+
+impl ItemSet {
+    fn new() -> Self {
+        ItemSet(0)
+    }
+
+    fn from_items(items: &str) -> Result<Self> {
+        let mut set = ItemSet::new();
+
+        for item in items.chars() {
+            set.insert(item)?;
+        }
+
+        Ok(set)
+    }
+
+    fn insert(&mut self, item: char) -> Result<()> {
+        if !item.is_ascii_alphabetic() {
+            bail!("Invalid rucksack item: {:?}", item);
+        }
+
+        self.0 |= 1 << (item_value(item) - 1);
+
+        Ok(())
+    }
+
+    fn contains(&self, item: char) -> bool {
+        item.is_ascii_alphabetic() && self.0 & (1 << (item_value(item) - 1)) != 0
+    }
+
+    fn union(self, other: ItemSet) -> ItemSet {
+        ItemSet(self.0 | other.0)
+    }
+
+    fn intersection(self, other: ItemSet) -> ItemSet {
+        ItemSet(self.0 & other.0)
+    }
+
+    fn difference(self, other: ItemSet) -> ItemSet {
+        ItemSet(self.0 & !other.0)
+    }
+
+    fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    fn items(&self) -> impl Iterator<Item = char> + '_ {
+        (1..=52)
+            .filter(|value| self.0 & (1 << (value - 1)) != 0)
+            .map(value_item)
+    }
+
+    fn priority(&self) -> i32 {
+        self.items().map(item_value).sum()
+    }
+}
+
+impl BitOr for ItemSet {
+    type Output = ItemSet;
+
+    fn bitor(self, rhs: ItemSet) -> ItemSet {
+        self.union(rhs)
+    }
+}
+
+impl BitAnd for ItemSet {
+    type Output = ItemSet;
+
+    fn bitand(self, rhs: ItemSet) -> ItemSet {
+        self.intersection(rhs)
+    }
+}
+
+impl Sub for ItemSet {
+    type Output = ItemSet;
+
+    fn sub(self, rhs: ItemSet) -> ItemSet {
+        self.difference(rhs)
+    }
+}
+
+/// Finds the single item shared by every rucksack in a group, erroring if there is no common
+/// item or more than one.
+fn find_badge(group: &[&str]) -> Result<char> {
+    let mut common = ItemSet::from_items(group.first().context("Empty group")?)?;
+
+    for rucksack in &group[1..] {
+        common = common & ItemSet::from_items(rucksack)?;
+    }
+
+    match common.len() {
+        1 => Ok(common.items().next().unwrap()),
+        0 => bail!("Group has no common item: {:?}", group),
+        _ => bail!(
+            "Group has multiple common items ({}): {:?}",
+            common.items().collect::<String>(),
+            group
+        ),
+    }
+}
+
+fn badge_sum(input: &str, group_size: usize) -> Result<i32> {
+    if group_size == 0 {
+        bail!("Group size must be at least 1");
+    }
+
+    let lines = input.lines().collect::<Vec<&str>>();
+
+    // A short last group would otherwise be accepted whenever its few rucksacks share one item
+    if lines.len() % group_size != 0 {
+        bail!(
+            "{} rucksacks cannot be split into groups of {}",
+            lines.len(),
+            group_size
+        );
+    }
+
+    lines
+        .chunks(group_size)
+        .map(|group| find_badge(group).map(item_value))
+        .sum()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Violation {
+    OddLength(usize),
+    InvalidItem { column: usize, item: char },
+    SharedItems(String),
+    IncompleteGroup { lines: usize, group_size: usize },
+}
+
+/// A single problem found in a rucksack list, tied to the (1-based) line it was found on.
+#[derive(Debug, PartialEq, Eq)]
+struct Diagnostic {
+    line: usize,
+    violation: Violation,
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+
+        match &self.violation {
+            Violation::OddLength(len) => write!(f, "rucksack has odd length {}", len),
+            Violation::InvalidItem { column, item } => {
+                write!(f, "invalid item {:?} at column {}", item, column)
+            }
+            Violation::SharedItems(items) if items.is_empty() => {
+                write!(f, "compartments share no items")
+            }
+            Violation::SharedItems(items) => {
+                write!(f, "compartments share multiple items ({})", items)
+            }
+            Violation::IncompleteGroup { lines, group_size } => write!(
+                f,
+                "{} rucksacks cannot be split into groups of {}",
+                lines, group_size
+            ),
+        }
+    }
+}
+
+/// Checks every rucksack in the input, collecting all violations instead of stopping at the
+/// first one.
+fn validate(input: &str, group_size: usize) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    let lines = input.lines().collect::<Vec<&str>>();
+
+    for (i, line) in lines.iter().enumerate() {
+        let line_number = i + 1;
+        let mut valid = true;
+
+        if line.len() % 2 != 0 {
+            valid = false;
+            diagnostics.push(Diagnostic {
+                line: line_number,
+                violation: Violation::OddLength(line.len()),
+            });
+        }
+
+        for (column, item) in line.chars().enumerate() {
+            if !item.is_ascii_alphabetic() {
+                valid = false;
+                diagnostics.push(Diagnostic {
+                    line: line_number,
+                    violation: Violation::InvalidItem {
+                        column: column + 1,
+                        item,
+                    },
+                });
+            }
+        }
+
+        if !valid {
+            continue;
+        }
+
+        let (first, second) = line.split_at(line.len() / 2);
+        let shared = ItemSet::from_items(first).unwrap() & ItemSet::from_items(second).unwrap();
+
+        if shared.len() != 1 {
+            diagnostics.push(Diagnostic {
+                line: line_number,
+                violation: Violation::SharedItems(shared.items().collect()),
+            });
+        }
+    }
+
+    // Nothing can be split into groups of zero, so the whole list is one incomplete group
+    let remainder = lines.len().checked_rem(group_size).unwrap_or(lines.len());
+    if remainder != 0 || group_size == 0 {
+        diagnostics.push(Diagnostic {
+            line: lines.len() - remainder + 1,
+            violation: Violation::IncompleteGroup {
+                lines: lines.len(),
+                group_size,
+            },
+        });
+    }
+
+    diagnostics
+}
+
+/// Like [`validate`], but folds every violation into a single error.
+fn check(input: &str, group_size: usize) -> Result<()> {
+    let diagnostics = validate(input, group_size);
+
+    if !diagnostics.is_empty() {
+        bail!(
+            "Found {} problem(s) in rucksack list:\n{}",
+            diagnostics.len(),
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<String>>()
+                .join("\n")
+        );
+    }
+
+    Ok(())
+}
+
 fn part1(input: &str) -> Result<i32> {
-    Ok(input
-        .split("\n")
+    input
+        .lines()
         .map(|line| line.split_at(line.len() / 2))
         .map(|(first, second)| {
-            for c in second.chars() {
-                if first.contains(c) {
-                    return item_value(c);
-                }
-            }
+            let shared = ItemSet::from_items(first)? & ItemSet::from_items(second)?;
 
-            return 0;
+            Ok(shared.priority())
         })
-        .sum())
+        .sum()
 }
 
 fn part2(input: &str) -> Result<i32> {
-    Ok(input
-        .split("\n")
-        .collect::<Vec<&str>>()
-        .chunks(3)
-        .map(|group| {
-            for c in group[0].chars() {
-                if group[1].contains(c) && group[2].contains(c) {
-                    return item_value(c);
-                }
-            }
-            return 0;
-        })
-        .sum())
+    badge_sum(input, 3)
 }
 
 #[cfg(test)]
@@ -51,7 +297,7 @@ mod tests_example {
 
     use crate::util;
 
-    const INPUT: &str = indoc! {"
+    pub(super) const INPUT: &str = indoc! {"
         vJrwpWtwJgWrhcsFMMfFFhFp
         jqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL
         PmmdzqPrVvPwwTWBwg
@@ -77,4 +323,133 @@ mod tests_example {
 
         Ok(())
     }
+
+    #[test]
+    fn test_badge_group_sizes() -> Result<()> {
+        let input = util::format_input(INPUT);
+
+        assert!(super::badge_sum(input, 2).is_err());
+        assert!(super::badge_sum(input, 6).is_err());
+        assert_eq!(super::badge_sum("abcX\nXdef\nXghi\nXjkl", 4)?, 50);
+        assert!(super::badge_sum(input, 0).is_err());
+
+        // The short last group has a single distinct item, which used to pass as its badge
+        let short =
+            "vJrwpWtwJgWrhcsFMMfFFhFp\njqHRNqRjqzjGDLGLrsFMfFZSrLrFZsSL\nPmmdzqPrVvPwwTWBwg\naa";
+        assert!(super::badge_sum(short, 3).is_err());
+        assert!(super::part2(short).is_err());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_item_set {
+    use anyhow::Result;
+
+    use super::ItemSet;
+
+    #[test]
+    fn test_set_operations() -> Result<()> {
+        let a = ItemSet::from_items("abcZ")?;
+        let b = ItemSet::from_items("cdZ")?;
+
+        assert_eq!((a | b).items().collect::<String>(), "abcdZ");
+        assert_eq!((a & b).items().collect::<String>(), "cZ");
+        assert_eq!((a - b).items().collect::<String>(), "ab");
+        assert_eq!((a & b).priority(), 3 + 52);
+
+        assert!(a.contains('Z'));
+        assert!(!a.contains('z'));
+        assert!(!a.contains('1'));
+        assert!((a - a).is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_item() {
+        assert!(ItemSet::from_items("ab1").is_err());
+    }
+
+    #[test]
+    fn test_badge_errors() {
+        assert!(super::find_badge(&["abc", "def", "ghi"]).is_err());
+        assert!(super::find_badge(&["abc", "abd", "abe"]).is_err());
+        assert_eq!(super::find_badge(&["abc", "cde", "cfg"]).unwrap(), 'c');
+    }
+}
+
+#[cfg(test)]
+mod tests_validate {
+    use indoc::indoc;
+
+    use super::{Diagnostic, Violation};
+    use crate::util;
+
+    #[test]
+    fn test_valid_input() {
+        assert!(super::check(util::format_input(super::tests_example::INPUT), 3).is_ok());
+    }
+
+    #[test]
+    fn test_reports_every_violation() {
+        let input = util::format_input(indoc! {"
+            abcxyc
+            abc
+            ab1a
+            abcabc
+        "});
+
+        let diagnostics = super::validate(input, 3);
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    line: 2,
+                    violation: Violation::OddLength(3)
+                },
+                Diagnostic {
+                    line: 3,
+                    violation: Violation::InvalidItem {
+                        column: 3,
+                        item: '1'
+                    }
+                },
+                Diagnostic {
+                    line: 4,
+                    violation: Violation::SharedItems("abc".to_string())
+                },
+                Diagnostic {
+                    line: 4,
+                    violation: Violation::IncompleteGroup {
+                        lines: 4,
+                        group_size: 3
+                    }
+                },
+            ]
+        );
+
+        let message = super::check(input, 3).unwrap_err().to_string();
+        assert!(message.contains("line 3: invalid item '1' at column 3"));
+        assert!(message.contains("line 4: 4 rucksacks cannot be split into groups of 3"));
+    }
+
+    #[test]
+    fn test_zero_group_size() {
+        let input = util::format_input(super::tests_example::INPUT);
+
+        assert_eq!(
+            super::validate(input, 0),
+            vec![Diagnostic {
+                line: 1,
+                violation: Violation::IncompleteGroup {
+                    lines: 6,
+                    group_size: 0
+                }
+            }]
+        );
+        assert!(super::check(input, 0).is_err());
+    }
 }
@@ -1,96 +1,190 @@
-use std::ops::Range;
+use anyhow::{Context, Result};
 
-use anyhow::Result;
+use crate::util::interval::{Interval, IntervalIndex, IntervalSet};
 
-//fn make_pairs(input: &str) -> Map {}
+type Pair = (Interval<u32>, Interval<u32>);
 
-fn range_fully_contains(base: &Range<u32>, check: &Range<u32>) -> bool {
-    let min = check.clone().min();
-    let max = check.clone().max();
+fn parse_assignment(elf: &str) -> Result<Interval<u32>> {
+    let (min, max) = elf.split_once('-').context("Missing section separator")?;
 
-    if min.is_none() || max.is_none() {
-        return false;
-    }
+    let min = min.parse::<u32>().context("Unable to parse number")?;
+    let max = max.parse::<u32>().context("Unable to parse number")?;
 
-    let min = min.unwrap();
-    let max = max.unwrap();
+    let end = max
+        .checked_add(1)
+        .with_context(|| format!("Section {} is too large", max))?;
 
-    base.contains(&min) && base.contains(&max)
+    Ok(Interval::new(min, end))
 }
 
-fn range_contains(base: &Range<u32>, check: &Range<u32>) -> bool {
-    let min = check.clone().min();
-    let max = check.clone().max();
+fn parse_pairs(input: &str) -> Result<Vec<Pair>> {
+    input
+        .lines()
+        .map(|line| {
+            let (first, second) = line.split_once(',').context("Missing pair separator")?;
 
-    if min.is_none() || max.is_none() {
-        return false;
-    }
+            Ok((parse_assignment(first)?, parse_assignment(second)?))
+        })
+        .collect()
+}
+
+/// Flattens pairs into a list of individual elf assignments. Elf `2 * n` and `2 * n + 1` are the
+/// two elves on line `n`.
+fn elves(pairs: &[Pair]) -> Vec<Interval<u32>> {
+    pairs.iter().flat_map(|(a, b)| [*a, *b]).collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct CoverageReport {
+    /// Sections between the lowest and highest assigned section that no elf covers
+    uncovered: IntervalSet<u32>,
+
+    /// Sections covered by more than the allowed number of elves
+    overcovered: IntervalSet<u32>,
 
-    let min = min.unwrap();
-    let max = max.unwrap();
+    /// Elves whose whole assignment is covered by a single other elf
+    redundant: Vec<usize>,
 
-    return base.contains(&min) || base.contains(&max);
+    /// A smallest set of elves that together cover every assigned section
+    minimum_cover: Vec<usize>,
 }
 
-fn part1(input: &str) -> Result<u32> {
-    Ok(input
-        .split("\n")
-        .map(|line| {
-            line.split(",")
-                .map(|elf| {
-                    let sections = elf
-                        .split("-")
-                        .map(|section| {
-                            u32::from_str_radix(section, 10).expect("Unable to parse number")
-                        })
-                        .collect::<Vec<u32>>();
-
-                    let min = sections[0];
-                    let max = sections[1] + 1;
-
-                    min..max
-                })
-                .collect::<Vec<Range<u32>>>()
-        })
-        .map(|pair| {
-            if range_fully_contains(&pair[0], &pair[1]) || range_fully_contains(&pair[1], &pair[0])
-            {
-                return 1;
+fn find_uncovered(elves: &[Interval<u32>]) -> IntervalSet<u32> {
+    let covered = elves.iter().copied().collect::<IntervalSet<u32>>();
+
+    let start = elves.iter().map(|elf| elf.start()).min();
+    let end = elves.iter().map(|elf| elf.end()).max();
+
+    match (start, end) {
+        (Some(start), Some(end)) => covered.complement(Interval::new(start, end)),
+        _ => IntervalSet::new(),
+    }
+}
+
+fn find_overcovered(elves: &[Interval<u32>], max_elves: usize) -> IntervalSet<u32> {
+    let mut events = elves
+        .iter()
+        .filter(|elf| !elf.is_empty())
+        .flat_map(|elf| [(elf.start(), 1), (elf.end(), -1)])
+        .collect::<Vec<(u32, i32)>>();
+    events.sort();
+
+    let mut overcovered = IntervalSet::new();
+    let mut count = 0;
+
+    for (i, (section, delta)) in events.iter().enumerate() {
+        count += delta;
+
+        // Only look at the count once every event at this section has been applied
+        if let Some((next, _)) = events.get(i + 1) {
+            if next != section && count > max_elves as i32 {
+                overcovered.insert(Interval::new(*section, *next));
             }
+        }
+    }
 
-            return 0;
-        })
-        .sum())
+    overcovered
 }
 
-fn part2(input: &str) -> Result<u32> {
-    Ok(input
-        .split("\n")
-        .map(|line| {
-            line.split(",")
-                .map(|elf| {
-                    let sections = elf
-                        .split("-")
-                        .map(|section| {
-                            u32::from_str_radix(section, 10).expect("Unable to parse number")
-                        })
-                        .collect::<Vec<u32>>();
-
-                    let min = sections[0];
-                    let max = sections[1] + 1;
-
-                    min..max
-                })
-                .collect::<Vec<Range<u32>>>()
-        })
-        .map(|pair| {
-            if range_contains(&pair[0], &pair[1]) || range_contains(&pair[1], &pair[0]) {
-                return 1;
+fn find_redundant(elves: &[Interval<u32>]) -> Vec<usize> {
+    let mut order = (0..elves.len())
+        .filter(|&i| !elves[i].is_empty())
+        .collect::<Vec<usize>>();
+
+    // Sorting by start and then by descending end means any elf that contains another comes
+    // before it. Identical assignments keep their original order, so only the later ones are
+    // redundant.
+    order.sort_by_key(|&i| (elves[i].start(), std::cmp::Reverse(elves[i].end()), i));
+
+    let mut redundant = Vec::new();
+    let mut furthest_end = None;
+
+    for i in order {
+        let end = elves[i].end();
+
+        if furthest_end.is_some_and(|furthest| furthest >= end) {
+            redundant.push(i);
+        } else {
+            furthest_end = Some(end);
+        }
+    }
+
+    redundant.sort();
+    redundant
+}
+
+fn find_minimum_cover(elves: &[Interval<u32>]) -> Vec<usize> {
+    let mut order = (0..elves.len())
+        .filter(|&i| !elves[i].is_empty())
+        .collect::<Vec<usize>>();
+    order.sort_by_key(|&i| (elves[i].start(), i));
+
+    let mut cover = Vec::new();
+    let mut covered_to: Option<u32> = None;
+    let mut i = 0;
+
+    loop {
+        // Skip any elves that are already completely covered
+        while i < order.len() && covered_to.is_some_and(|to| elves[order[i]].end() <= to) {
+            i += 1;
+        }
+
+        if i == order.len() {
+            break;
+        }
+
+        // The first section still needing cover, jumping over any gap in the assignments
+        let section = match covered_to {
+            Some(to) if elves[order[i]].start() <= to => to,
+            _ => elves[order[i]].start(),
+        };
+
+        // Out of all elves that cover this section, take the one that reaches the furthest
+        let mut best = order[i];
+        while i < order.len() && elves[order[i]].start() <= section {
+            if elves[order[i]].end() > elves[best].end() {
+                best = order[i];
             }
 
-            return 0;
-        })
-        .sum())
+            i += 1;
+        }
+
+        cover.push(best);
+        covered_to = Some(elves[best].end());
+    }
+
+    cover.sort();
+    cover
+}
+
+fn coverage_report(pairs: &[Pair], max_elves: usize) -> CoverageReport {
+    let elves = elves(pairs);
+
+    CoverageReport {
+        uncovered: find_uncovered(&elves),
+        overcovered: find_overcovered(&elves, max_elves),
+        redundant: find_redundant(&elves),
+        minimum_cover: find_minimum_cover(&elves),
+    }
+}
+
+/// Builds a query index over every elf's assignment, using the same elf numbering as [`elves`].
+fn assignment_index(pairs: &[Pair]) -> IntervalIndex<u32> {
+    IntervalIndex::new(&elves(pairs))
+}
+
+fn part1(input: &str) -> Result<u32> {
+    Ok(parse_pairs(input)?
+        .iter()
+        .filter(|(a, b)| a.contains_interval(b) || b.contains_interval(a))
+        .count() as u32)
+}
+
+fn part2(input: &str) -> Result<u32> {
+    Ok(parse_pairs(input)?
+        .iter()
+        .filter(|(a, b)| a.overlaps(b))
+        .count() as u32)
 }
 
 #[cfg(test)]
@@ -1121,3 +1215,121 @@ mod tests_example {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests_coverage {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::CoverageReport;
+    use crate::util::{
+        self,
+        interval::{Interval, IntervalSet},
+    };
+
+    const INPUT: &str = indoc! {"
+        2-4,6-8
+        2-3,4-5
+        5-7,7-9
+        2-8,3-7
+        6-6,4-6
+        2-6,4-8
+    "};
+
+    #[test]
+    fn test_coverage_report() -> Result<()> {
+        let pairs = super::parse_pairs(util::format_input(INPUT))?;
+        let report = super::coverage_report(&pairs, 4);
+
+        assert_eq!(
+            report,
+            CoverageReport {
+                uncovered: IntervalSet::new(),
+                overcovered: [Interval::new(3, 8)].into_iter().collect(),
+                redundant: vec![0, 1, 2, 3, 4, 7, 8, 9, 10, 11],
+                minimum_cover: vec![5, 6],
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_coverage_gaps() -> Result<()> {
+        let pairs = super::parse_pairs("1-3,3-5\n5-5,9-12\n10-14,9-12")?;
+        let report = super::coverage_report(&pairs, 1);
+
+        assert_eq!(
+            report.uncovered.iter().copied().collect::<Vec<_>>(),
+            vec![Interval::new(6, 9)]
+        );
+        assert_eq!(
+            report.overcovered.iter().copied().collect::<Vec<_>>(),
+            vec![
+                Interval::new(3, 4),
+                Interval::new(5, 6),
+                Interval::new(9, 13)
+            ]
+        );
+        assert_eq!(report.redundant, vec![2, 5]);
+        assert_eq!(report.minimum_cover, vec![0, 1, 3, 4]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_section_too_large() {
+        assert!(super::parse_pairs("1-4294967294,1-2").is_ok());
+        assert!(super::parse_pairs("1-4294967295,1-2").is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests_index {
+    use super::Pair;
+    use crate::util::{interval::Interval, rng::Rng};
+
+    fn random_pairs(rng: &mut Rng, count: usize) -> Vec<Pair> {
+        let mut assignment = || {
+            let min = rng.range(1, 1000) as u32;
+            let max = min + rng.range(0, 50) as u32;
+
+            Interval::new(min, max + 1)
+        };
+
+        (0..count).map(|_| (assignment(), assignment())).collect()
+    }
+
+    #[test]
+    fn test_index_matches_brute_force() {
+        let mut rng = Rng::new(4);
+        let pairs = random_pairs(&mut rng, 500);
+        let elves = super::elves(&pairs);
+        let index = super::assignment_index(&pairs);
+
+        for _ in 0..200 {
+            let min = rng.range(0, 1100) as u32;
+            let query = Interval::new(min, min + rng.range(1, 30) as u32);
+
+            let expected = (0..elves.len())
+                .filter(|&i| elves[i].overlaps(&query))
+                .collect::<Vec<usize>>();
+            assert_eq!(index.overlapping(query), expected);
+
+            let expected = (0..elves.len())
+                .filter(|&i| elves[i].contains(&min))
+                .collect::<Vec<usize>>();
+            assert_eq!(index.containing(min), expected);
+        }
+
+        let mut expected = 0;
+        for a in 0..elves.len() {
+            for b in (a + 1)..elves.len() {
+                if elves[a].overlaps(&elves[b]) {
+                    expected += 1;
+                }
+            }
+        }
+        assert_eq!(index.overlapping_pairs(), expected);
+    }
+}
@@ -1,160 +1,683 @@
-use anyhow::{Context, Result};
+use std::{fmt::Display, fs, path::Path};
 
-type Stack = Vec<char>;
+use anyhow::{bail, Context, Result};
 
-fn parse_header(input: &str) -> Result<Vec<Stack>> {
+type Crate = String;
+type Stack = Vec<Crate>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Stacks {
+    stacks: Vec<Stack>,
+}
+
+impl Stacks {
+    fn new(stacks: Vec<Stack>) -> Self {
+        Stacks { stacks }
+    }
+
+    /// Removes the top `count` crates from a stack, returned in bottom to top order.
+    fn take(&mut self, from: usize, count: usize) -> Result<Vec<Crate>> {
+        let stack = self
+            .stacks
+            .get_mut(from)
+            .with_context(|| format!("Stack {} does not exist", from + 1))?;
+
+        let end = stack.len().checked_sub(count).with_context(|| {
+            format!(
+                "Attempted to move {} crates from stack {} with only {}",
+                count,
+                from + 1,
+                stack.len()
+            )
+        })?;
+
+        Ok(stack.split_off(end))
+    }
+
+    /// Places crates onto a stack, with the last crate ending up on top.
+    fn put(&mut self, to: usize, mut crates: Vec<Crate>) -> Result<()> {
+        self.stacks
+            .get_mut(to)
+            .with_context(|| format!("Stack {} does not exist", to + 1))?
+            .append(&mut crates);
+
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.stacks.len()
+    }
+
+    fn get(&self, i: usize) -> Option<&Stack> {
+        self.stacks.get(i)
+    }
+
+    fn set(&mut self, i: usize, stack: Stack) {
+        if let Some(existing) = self.stacks.get_mut(i) {
+            *existing = stack;
+        }
+    }
+
+    /// The crate on top of each stack, skipping empty stacks.
+    fn top(&self) -> String {
+        self.stacks
+            .iter()
+            .filter_map(|stack| stack.last().map(|c| c.as_str()))
+            .collect::<String>()
+    }
+}
+
+/// Renders the stacks in the same drawing format as the puzzle input, so that the output can be
+/// parsed back with `parse_header`.
+impl Display for Stacks {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let height = self
+            .stacks
+            .iter()
+            .map(|stack| stack.len())
+            .max()
+            .unwrap_or(0);
+
+        // Every column is wide enough for its biggest crate and its number
+        let widths = self
+            .stacks
+            .iter()
+            .enumerate()
+            .map(|(i, stack)| {
+                stack
+                    .iter()
+                    .map(|c| c.chars().count() + 2)
+                    .chain([3, (i + 1).to_string().len()])
+                    .max()
+                    .unwrap()
+            })
+            .collect::<Vec<usize>>();
+
+        for row in (0..height).rev() {
+            let line = self
+                .stacks
+                .iter()
+                .zip(&widths)
+                .map(|(stack, width)| match stack.get(row) {
+                    Some(c) => center(&format!("[{}]", c), *width),
+                    None => " ".repeat(*width),
+                })
+                .collect::<Vec<String>>()
+                .join(" ");
+
+            writeln!(f, "{}", line)?;
+        }
+
+        let base = widths
+            .iter()
+            .enumerate()
+            .map(|(i, width)| center(&(i + 1).to_string(), *width))
+            .collect::<Vec<String>>()
+            .join(" ");
+
+        write!(f, "{}", base)
+    }
+}
+
+fn center(text: &str, width: usize) -> String {
+    let padding = width.saturating_sub(text.chars().count());
+    let left = padding / 2;
+
+    format!("{}{}{}", " ".repeat(left), text, " ".repeat(padding - left))
+}
+
+/// A model of crane, deciding how a move instruction rearranges the stacks.
+trait Crane {
+    fn apply(&mut self, stacks: &mut Stacks, instruction: &Instruction) -> Result<()>;
+}
+
+/// Moves crates one at a time, so the moved crates end up in reverse order.
+#[derive(Clone)]
+struct CrateMover9000;
+
+impl Crane for CrateMover9000 {
+    fn apply(&mut self, stacks: &mut Stacks, instruction: &Instruction) -> Result<()> {
+        let mut crates = stacks.take(instruction.from, instruction.count)?;
+        crates.reverse();
+
+        stacks.put(instruction.to, crates)
+    }
+}
+
+/// Moves all crates at once, keeping them in the same order.
+#[derive(Clone)]
+struct CrateMover9001;
+
+impl Crane for CrateMover9001 {
+    fn apply(&mut self, stacks: &mut Stacks, instruction: &Instruction) -> Result<()> {
+        let crates = stacks.take(instruction.from, instruction.count)?;
+
+        stacks.put(instruction.to, crates)
+    }
+}
+
+/// Like the CrateMover 9001, but can only lift `capacity` crates at a time, so large moves are
+/// split into several lifts.
+#[derive(Clone)]
+struct LimitedCrane {
+    capacity: usize,
+}
+
+impl Crane for LimitedCrane {
+    fn apply(&mut self, stacks: &mut Stacks, instruction: &Instruction) -> Result<()> {
+        if self.capacity == 0 {
+            bail!("Crane can't lift anything with a capacity of 0");
+        }
+
+        let mut remaining = instruction.count;
+
+        while remaining > 0 {
+            let lift = remaining.min(self.capacity);
+            let crates = stacks.take(instruction.from, lift)?;
+            stacks.put(instruction.to, crates)?;
+
+            remaining -= lift;
+        }
+
+        Ok(())
+    }
+}
+
+/// Moves all crates at once, but drops them in reverse order on every other move, starting with
+/// the second.
+#[derive(Clone, Default)]
+struct AlternatingCrane {
+    moves: usize,
+}
+
+impl Crane for AlternatingCrane {
+    fn apply(&mut self, stacks: &mut Stacks, instruction: &Instruction) -> Result<()> {
+        let mut crates = stacks.take(instruction.from, instruction.count)?;
+
+        if self.moves % 2 == 1 {
+            crates.reverse();
+        }
+        self.moves += 1;
+
+        stacks.put(instruction.to, crates)
+    }
+}
+
+/// Splits a line into its whitespace separated tokens, along with the span of character
+/// positions each one covers.
+fn tokens(line: &str) -> Vec<(usize, usize, String)> {
+    let mut tokens = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+
+    for (i, c) in line.chars().chain([' ']).enumerate() {
+        match (c.is_whitespace(), &mut current) {
+            (false, Some((_, token))) => token.push(c),
+            (false, None) => current = Some((i, c.to_string())),
+            (true, Some(_)) => {
+                let (start, token) = current.take().unwrap();
+                tokens.push((start, i, token));
+            }
+            (true, None) => {}
+        }
+    }
+
+    tokens
+}
+
+fn parse_header(input: &str) -> Result<Stacks> {
     // Extract the header from our input
     let header = input
         .lines()
         .take_while(|line| !line.is_empty())
         .collect::<Vec<&str>>();
 
-    // Get the columns
-    let columns = header
-        .last()
-        .context("Could not find column base line")?
-        .chars()
+    // Get the columns, along with the character position at the center of each label
+    let columns = tokens(header.last().context("Could not find column base line")?)
+        .into_iter()
         .enumerate()
-        .filter(|(i, c)| c.is_numeric())
-        .map(|(i, c)| {
-            Ok((
-                i,
-                usize::from_str_radix(&c.to_string(), 10)
-                    .context("Failed to parse column number")?,
-            ))
+        .map(|(i, (start, end, label))| {
+            let number = usize::from_str_radix(&label, 10)
+                .with_context(|| format!("Failed to parse column number {:?}", label))?;
+
+            if number != i + 1 {
+                bail!("Expected column {} but found {}", i + 1, number);
+            }
+
+            Ok((start + end) / 2)
         })
-        .collect::<Result<Vec<(usize, usize)>>>()?;
+        .collect::<Result<Vec<usize>>>()?;
 
-    let mut stacks = Vec::<Stack>::with_capacity(columns.len());
+    let mut stacks = vec![Stack::new(); columns.len()];
 
-    let rows = header
-        .iter()
-        .rev()
-        .skip(1)
-        .map(|row| row.chars().collect::<Vec<char>>())
-        .collect::<Vec<Vec<char>>>();
-    for (i, col) in columns {
-        let mut stack = Vec::new();
-
-        for row in &rows {
-            // Handle if lines that end in spaces get trimmed
-            if row.len() <= i {
-                continue;
-            }
+    for (row, line) in header.iter().rev().skip(1).enumerate() {
+        let mut filled = vec![false; columns.len()];
+
+        for (start, end, token) in tokens(line) {
+            let label = token
+                .strip_prefix('[')
+                .and_then(|token| token.strip_suffix(']'))
+                .filter(|label| !label.is_empty())
+                .with_context(|| format!("Invalid crate {:?}", token))?;
 
-            let c = row[i];
+            // A crate belongs to whichever column's label sits underneath it
+            let col = columns
+                .iter()
+                .position(|center| (start..end).contains(center))
+                .with_context(|| format!("Crate {} is not above any column", token))?;
 
-            // Don't add whitespace chars
-            if !c.is_alphabetic() {
-                continue;
+            if filled[col] {
+                bail!("Multiple crates above column {} in the same row", col + 1);
             }
+            filled[col] = true;
 
-            stack.push(c);
-        }
+            if stacks[col].len() != row {
+                bail!("Crate {} in column {} is floating", token, col + 1);
+            }
 
-        stacks.push(stack);
+            stacks[col].push(label.to_string());
+        }
     }
 
-    Ok(stacks)
+    Ok(Stacks::new(stacks))
 }
 
-#[derive(Debug)]
-
+#[derive(Debug, Clone)]
 struct Instruction {
     count: usize,
     from: usize,
     to: usize,
+
+    // The line of the input this instruction came from
+    line: usize,
+}
+
+fn parse_stack_number(part: Option<&str>) -> Result<usize> {
+    usize::from_str_radix(part.context("Missing stack number")?, 10)?
+        .checked_sub(1)
+        .context("Stack numbers start at 1")
 }
 
 fn parse_instructions(input: &str) -> Result<Vec<Instruction>> {
     Ok(input
         .lines()
-        .skip_while(|line| !line.is_empty())
+        .enumerate()
+        .skip_while(|(_, line)| !line.is_empty())
         .skip(1)
-        .map(|line| {
+        .map(|(i, line)| {
             let mut part = line.split(" ");
-            let count = usize::from_str_radix(part.nth(1).context("Could not get count")?, 10)?;
-            let from = usize::from_str_radix(part.nth(1).context("Could not get from")?, 10)? - 1;
-            let to = usize::from_str_radix(part.nth(1).context("Could not get tos")?, 10)? - 1;
+            let count = usize::from_str_radix(part.nth(1).context("Could not get count")?, 10)
+                .with_context(|| format!("Invalid count on line {}", i + 1))?;
+            let from = parse_stack_number(part.nth(1))
+                .with_context(|| format!("Invalid from stack on line {}", i + 1))?;
+            let to = parse_stack_number(part.nth(1))
+                .with_context(|| format!("Invalid to stack on line {}", i + 1))?;
 
-            Ok(Instruction { count, from, to })
+            Ok(Instruction {
+                count,
+                from,
+                to,
+                line: i + 1,
+            })
         })
         .collect::<Result<Vec<Instruction>>>()?)
 }
 
-fn print_instruction(instruction: &Instruction) {
-    println!(
-        "move {} from {} to {}",
-        instruction.count,
-        instruction.from + 1,
-        instruction.to + 1
-    );
+impl Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "move {} from {} to {}",
+            self.count,
+            self.from + 1,
+            self.to + 1
+        )
+    }
 }
 
-fn print_stacks(stacks: &Vec<Stack>) {
-    let mut max_height = 0;
-    for col in stacks {
-        let len = col.len();
+/// The state needed to reverse a single instruction: the crane as it was before the move, and
+/// the two stacks it touched.
+struct Undo<C> {
+    crane: C,
+    from: Stack,
+    to: Stack,
+}
+
+/// Steps through a procedure one instruction at a time, keeping an undo log so that it can be
+/// wound back to any earlier step.
+struct Simulation<C> {
+    stacks: Stacks,
+    instructions: Vec<Instruction>,
+    crane: C,
+    undo_log: Vec<Undo<C>>,
+}
 
-        if len > max_height {
-            max_height = len;
+impl<C: Crane + Clone> Simulation<C> {
+    fn new(stacks: Stacks, instructions: Vec<Instruction>, crane: C) -> Self {
+        Simulation {
+            stacks,
+            instructions,
+            crane,
+            undo_log: Vec::new(),
         }
     }
 
-    for i in 0..stacks.len() {
-        print!(" {}  ", i + 1);
+    fn parse(input: &str, crane: C) -> Result<Self> {
+        Ok(Simulation::new(
+            parse_header(input)?,
+            parse_instructions(input)?,
+            crane,
+        ))
+    }
+
+    /// The number of instructions that have been applied so far.
+    fn step(&self) -> usize {
+        self.undo_log.len()
+    }
+
+    fn len(&self) -> usize {
+        self.instructions.len()
+    }
+
+    fn stacks(&self) -> &Stacks {
+        &self.stacks
+    }
+
+    /// Applies the next instruction, returning false if the procedure is already finished. If the
+    /// instruction fails, the stacks are left as they were before it.
+    fn forward(&mut self) -> Result<bool> {
+        let step = self.step();
+        let instruction = match self.instructions.get(step) {
+            Some(instruction) => instruction,
+            None => return Ok(false),
+        };
+
+        let undo = Undo {
+            crane: self.crane.clone(),
+            from: self
+                .stacks
+                .get(instruction.from)
+                .cloned()
+                .unwrap_or_default(),
+            to: self.stacks.get(instruction.to).cloned().unwrap_or_default(),
+        };
+
+        if let Err(err) = self.crane.apply(&mut self.stacks, instruction) {
+            let context = format!("Step {}: {}", step + 1, self.instructions[step]);
+            self.restore(undo);
+
+            return Err(err.context(context));
+        }
+
+        self.undo_log.push(undo);
+
+        Ok(true)
     }
-    println!("");
 
-    for row in 0..max_height {
-        for col in stacks.iter() {
-            if col.len() > row {
-                print!("[{}] ", col[row]);
-            } else {
-                print!("    ");
+    /// Reverts the last applied instruction, returning false if we're already at the start.
+    fn back(&mut self) -> bool {
+        match self.undo_log.pop() {
+            Some(undo) => {
+                self.restore(undo);
+                true
             }
+            None => false,
+        }
+    }
+
+    fn restore(&mut self, undo: Undo<C>) {
+        let instruction = &self.instructions[self.step()];
+
+        self.stacks.set(instruction.to, undo.to);
+        self.stacks.set(instruction.from, undo.from);
+        self.crane = undo.crane;
+    }
+
+    /// Moves forwards or backwards until exactly `step` instructions have been applied.
+    fn seek(&mut self, step: usize) -> Result<()> {
+        if step > self.len() {
+            bail!("Step {} is past the end of the procedure", step);
+        }
+
+        while self.step() > step {
+            self.back();
+        }
+
+        while self.step() < step {
+            self.forward()?;
+        }
+
+        Ok(())
+    }
+
+    /// Renders the current step, the instruction that led to it and the stacks.
+    fn render(&self) -> String {
+        let description = match self.step() {
+            0 => "start".to_string(),
+            step => self.instructions[step - 1].to_string(),
+        };
+
+        format!(
+            "Step {}/{}: {}\n\n{}",
+            self.step(),
+            self.len(),
+            description,
+            self.stacks
+        )
+    }
+
+    /// Renders every step of the procedure from the start, leaving the simulation at the end.
+    fn frames(&mut self) -> Result<Vec<String>> {
+        self.seek(0)?;
+
+        let mut frames = vec![self.render()];
+        while self.forward()? {
+            frames.push(self.render());
         }
-        println!("");
+
+        Ok(frames)
     }
 }
 
-fn part1(input: &str) -> Result<String> {
-    let mut stacks = parse_header(input)?;
-    let instructions = parse_instructions(input)?;
+const FRAME_SEPARATOR: &str = "\n----\n";
+
+/// Writes each frame to its own numbered file in `dir`, creating it if needed.
+fn write_frames_to_dir(frames: &[String], dir: &Path) -> Result<()> {
+    fs::create_dir_all(dir)?;
+
+    for (i, frame) in frames.iter().enumerate() {
+        let path = dir.join(format!("frame{:04}.txt", i));
+        fs::write(&path, format!("{}\n", frame))
+            .with_context(|| format!("Failed to write frame to {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Writes all frames into a single file, separated by a line of dashes.
+fn write_frames_to_file(frames: &[String], path: &Path) -> Result<()> {
+    fs::write(path, format!("{}\n", frames.join(FRAME_SEPARATOR)))
+        .with_context(|| format!("Failed to write frames to {}", path.display()))
+}
+
+/// The first instruction in a procedure that can't be carried out.
+#[derive(Debug)]
+struct IllegalMove {
+    instruction: Instruction,
+    reason: String,
+
+    // The stacks just before the instruction
+    stacks: Stacks,
+}
+
+impl Display for IllegalMove {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "line {}: {}: {}\n\n{}",
+            self.instruction.line, self.instruction, self.reason, self.stacks
+        )
+    }
+}
+
+fn check_instruction(stacks: &Stacks, instruction: &Instruction) -> Option<String> {
+    for (name, i) in [("from", instruction.from), ("to", instruction.to)] {
+        if i >= stacks.len() {
+            return Some(format!(
+                "{} stack {} does not exist, there are only {} stacks",
+                name,
+                i + 1,
+                stacks.len()
+            ));
+        }
+    }
+
+    let height = stacks.get(instruction.from).map_or(0, |stack| stack.len());
+    if instruction.count > height {
+        return Some(format!(
+            "cannot move {} crates from stack {} which only has {}",
+            instruction.count,
+            instruction.from + 1,
+            height
+        ));
+    }
+
+    None
+}
+
+/// Runs the procedure with the given crane, checking each instruction against the stacks as they
+/// are at that point. Returns the first illegal move, if any.
+fn validate<C: Crane + Clone>(input: &str, crane: C) -> Result<Option<IllegalMove>> {
+    let mut simulation = Simulation::parse(input, crane)?;
+
+    while simulation.step() < simulation.len() {
+        let instruction = &simulation.instructions[simulation.step()];
+
+        if let Some(reason) = check_instruction(simulation.stacks(), instruction) {
+            return Ok(Some(IllegalMove {
+                instruction: instruction.clone(),
+                reason,
+                stacks: simulation.stacks().clone(),
+            }));
+        }
+
+        simulation.forward()?;
+    }
+
+    Ok(None)
+}
+
+/// Runs a procedure from the given starting stacks with a fresh copy of the crane, returning the
+/// final stacks.
+fn simulate<C: Crane + Clone>(
+    stacks: &Stacks,
+    instructions: &[Instruction],
+    crane: &C,
+) -> Result<Stacks> {
+    let mut stacks = stacks.clone();
+    let mut crane = crane.clone();
 
     for instruction in instructions {
-        for _ in 0..instruction.count {
-            let c = stacks[instruction.from]
-                .pop()
-                .context("Attempted to move crate from empty stack")?;
-            stacks[instruction.to].push(c);
+        crane.apply(&mut stacks, instruction)?;
+    }
+
+    Ok(stacks)
+}
+
+/// Tries to shorten a procedure while keeping the same final stacks, by repeatedly deleting
+/// single instructions or adjacent pairs and merging neighbouring moves between the same stacks.
+/// Every candidate is checked by simulating it with the given crane, so this works for any crane
+/// model, but the result is not guaranteed to be the shortest possible.
+fn optimize<C: Crane + Clone>(
+    stacks: &Stacks,
+    instructions: &[Instruction],
+    crane: &C,
+) -> Result<Vec<Instruction>> {
+    let target = simulate(stacks, instructions, crane)?;
+    let equivalent = |candidate: &[Instruction]| {
+        simulate(stacks, candidate, crane).is_ok_and(|result| result == target)
+    };
+
+    let mut best = instructions.to_vec();
+
+    loop {
+        let mut improved = false;
+
+        // Drop any instruction that isn't needed, along with pairs of moves that undo each other
+        for window in 1..=2 {
+            let mut i = 0;
+            while i + window <= best.len() {
+                let mut candidate = best.clone();
+                candidate.drain(i..i + window);
+
+                if equivalent(&candidate) {
+                    best = candidate;
+                    improved = true;
+                } else {
+                    i += 1;
+                }
+            }
         }
+
+        // Merge moves between the same pair of stacks into one
+        let mut i = 0;
+        while i + 1 < best.len() {
+            let (a, b) = (&best[i], &best[i + 1]);
+
+            if a.from == b.from && a.to == b.to {
+                let mut candidate = best.clone();
+                candidate[i].count += b.count;
+                candidate.remove(i + 1);
+
+                if equivalent(&candidate) {
+                    best = candidate;
+                    improved = true;
+                    continue;
+                }
+            }
+
+            i += 1;
+        }
+
+        if !improved {
+            break;
+        }
+    }
+
+    if simulate(stacks, &best, crane)? != target {
+        bail!("Optimized procedure does not reach the same stacks");
     }
 
-    let top = stacks
+    Ok(best)
+}
+
+/// Renders the stacks and procedure back into the puzzle input format.
+fn render_input(stacks: &Stacks, instructions: &[Instruction]) -> String {
+    let procedure = instructions
         .iter()
-        .filter_map(|stack| stack.last())
-        .collect::<String>();
+        .map(|instruction| instruction.to_string())
+        .collect::<Vec<String>>()
+        .join("\n");
 
-    Ok(top)
+    format!("{}\n\n{}", stacks, procedure)
 }
 
-fn part2(input: &str) -> Result<String> {
+fn rearrange(input: &str, crane: &mut impl Crane) -> Result<String> {
     let mut stacks = parse_header(input)?;
     let instructions = parse_instructions(input)?;
 
-    for instruction in instructions {
-        let end = stacks[instruction.from].len();
-        let mut grabbed = stacks[instruction.from].split_off(end - instruction.count);
-        stacks[instruction.to].append(&mut grabbed);
+    for instruction in &instructions {
+        crane.apply(&mut stacks, instruction)?;
     }
 
-    let top = stacks
-        .iter()
-        .filter_map(|stack| stack.last())
-        .collect::<String>();
+    Ok(stacks.top())
+}
+
+fn part1(input: &str) -> Result<String> {
+    rearrange(input, &mut CrateMover9000)
+}
 
-    Ok(top)
+fn part2(input: &str) -> Result<String> {
+    rearrange(input, &mut CrateMover9001)
 }
 
 #[cfg(test)]
@@ -162,7 +685,7 @@ mod tests_example {
     use anyhow::Result;
     use indoc::indoc;
 
-    const INPUT: &str = indoc! {"
+    pub(super) const INPUT: &str = indoc! {"
             [D]    
         [N] [C]    
         [Z] [M] [P]
@@ -191,4 +714,364 @@ mod tests_example {
 
         Ok(())
     }
+
+    #[test]
+    fn test_limited_crane() -> Result<()> {
+        // A capacity of one behaves just like the CrateMover 9000
+        let result = super::rearrange(INPUT, &mut super::LimitedCrane { capacity: 1 })?;
+        assert_eq!(result, "CMZ");
+
+        // The three crate move is split into a lift of two and then a lift of one
+        let result = super::rearrange(INPUT, &mut super::LimitedCrane { capacity: 2 })?;
+        assert_eq!(result, "MCZ");
+
+        assert!(super::rearrange(INPUT, &mut super::LimitedCrane { capacity: 0 }).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_alternating_crane() -> Result<()> {
+        let result = super::rearrange(INPUT, &mut super::AlternatingCrane::default())?;
+
+        assert_eq!(result, "MCZ");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_render {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::Stacks;
+    use crate::util::rng::Rng;
+
+    #[test]
+    fn test_render_matches_input() -> Result<()> {
+        let drawing = indoc! {"
+                [D]    
+            [N] [C]    
+            [Z] [M] [P]
+             1   2   3 "};
+
+        let stacks = super::parse_header(drawing)?;
+
+        assert_eq!(stacks.to_string(), drawing);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_many_columns() -> Result<()> {
+        let drawing = indoc! {"
+                                                        [X]
+            [A] [B] [C] [D] [E] [F] [G] [H] [I] [J] [K] [L]
+             1   2   3   4   5   6   7   8   9   10  11  12
+        "};
+
+        let stacks = super::parse_header(drawing)?;
+
+        assert_eq!(stacks.len(), 12);
+        assert_eq!(stacks.top(), "ABCDEFGHIJKX");
+        assert_eq!(super::parse_header(&stacks.to_string())?, stacks);
+
+        let input = format!("{}\nmove 2 from 12 to 10\n", drawing);
+        assert_eq!(
+            super::rearrange(&input, &mut super::CrateMover9001)?,
+            "ABCDEFGHIXK"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_wide_labels() -> Result<()> {
+        let drawing = indoc! {"
+            [AB]      [CDE]
+            [X]  [Y]  [Z]
+              1    2    3
+        "};
+
+        let stacks = super::parse_header(drawing)?;
+
+        assert_eq!(stacks.get(0).unwrap(), &["X", "AB"]);
+        assert_eq!(stacks.get(2).unwrap(), &["Z", "CDE"]);
+        assert_eq!(
+            stacks.to_string(),
+            "[AB]     [CDE]\n[X]  [Y]  [Z] \n 1    2    3  "
+        );
+        assert_eq!(super::parse_header(&stacks.to_string())?, stacks);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_drawings() {
+        assert!(super::parse_header("[A] [B]\n 1 ").is_err());
+        assert!(super::parse_header("[A]\n 2 ").is_err());
+        assert!(super::parse_header("    [A]\n 1   2 ").is_ok());
+        assert!(super::parse_header("[A]\n    \n 1 ").is_err());
+        assert!(super::parse_header("A\n 1 ").is_err());
+    }
+
+    #[test]
+    fn test_round_trip_random_stacks() -> Result<()> {
+        let mut rng = Rng::new(5);
+
+        for _ in 0..200 {
+            let columns = rng.range(1, 16) as usize;
+            let stacks = Stacks::new(
+                (0..columns)
+                    .map(|_| {
+                        let height = rng.range(0, 8);
+
+                        (0..height)
+                            .map(|_| {
+                                (0..rng.range(1, 4))
+                                    .map(|_| (b'A' + rng.range(0, 26) as u8) as char)
+                                    .collect()
+                            })
+                            .collect()
+                    })
+                    .collect(),
+            );
+
+            assert_eq!(super::parse_header(&stacks.to_string())?, stacks);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_simulation {
+    use std::fs;
+
+    use anyhow::Result;
+
+    use super::{CrateMover9000, CrateMover9001, Simulation};
+    use crate::util::temp_dir::TempDir;
+
+    const INPUT: &str = super::tests_example::INPUT;
+
+    #[test]
+    fn test_seek() -> Result<()> {
+        let mut simulation = Simulation::parse(INPUT, CrateMover9001)?;
+        let start = simulation.stacks().clone();
+
+        simulation.seek(4)?;
+        assert_eq!(simulation.stacks().top(), "MCD");
+        let end = simulation.stacks().clone();
+
+        simulation.seek(2)?;
+        assert_eq!(simulation.stacks().top(), "CD");
+
+        assert!(simulation.back());
+        assert!(simulation.back());
+        assert!(!simulation.back());
+        assert_eq!(simulation.stacks(), &start);
+
+        simulation.seek(4)?;
+        assert_eq!(simulation.stacks(), &end);
+        assert!(!simulation.forward()?);
+        assert!(simulation.seek(5).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_undo_stateful_crane() -> Result<()> {
+        let mut simulation = Simulation::parse(INPUT, super::AlternatingCrane::default())?;
+
+        simulation.seek(4)?;
+        let end = simulation.stacks().clone();
+
+        simulation.seek(1)?;
+        simulation.seek(4)?;
+        assert_eq!(simulation.stacks(), &end);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_failed_step_leaves_stacks() -> Result<()> {
+        let input = "[A]\n 1   2 \n\nmove 2 from 1 to 2";
+        let mut simulation = Simulation::parse(input, super::LimitedCrane { capacity: 1 })?;
+        let start = simulation.stacks().clone();
+
+        assert!(simulation.forward().is_err());
+        assert_eq!(simulation.stacks(), &start);
+        assert_eq!(simulation.step(), 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_frames() -> Result<()> {
+        let mut simulation = Simulation::parse(INPUT, CrateMover9000)?;
+        let frames = simulation.frames()?;
+
+        assert_eq!(frames.len(), 5);
+        assert!(frames[0].starts_with("Step 0/4: start\n\n"));
+        assert_eq!(
+            frames[4],
+            "Step 4/4: move 1 from 1 to 2\n\n        [Z]\n        [N]\n        [D]\n[C] [M] [P]\n 1   2   3 "
+        );
+
+        let temp = TempDir::new("day05")?;
+        let dir = temp.path().join("frames");
+        super::write_frames_to_dir(&frames, &dir)?;
+        assert_eq!(
+            fs::read_to_string(dir.join("frame0004.txt"))?,
+            frames[4].clone() + "\n"
+        );
+
+        let file = dir.join("all.txt");
+        super::write_frames_to_file(&frames, &file)?;
+        assert_eq!(fs::read_to_string(&file)?.matches("\n----\n").count(), 4);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_validate {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{CrateMover9000, CrateMover9001};
+
+    #[test]
+    fn test_valid_procedure() -> Result<()> {
+        let input = super::tests_example::INPUT;
+
+        assert!(super::validate(input, CrateMover9000)?.is_none());
+        assert!(super::validate(input, CrateMover9001)?.is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_too_many_crates() -> Result<()> {
+        let input = indoc! {"
+            [A]    
+            [B] [C]
+             1   2 
+
+            move 1 from 2 to 1
+            move 4 from 1 to 2
+        "};
+
+        for illegal in [
+            super::validate(input, CrateMover9000)?,
+            super::validate(input, CrateMover9001)?,
+        ] {
+            let illegal = illegal.unwrap();
+
+            assert_eq!(illegal.instruction.line, 6);
+            assert_eq!(illegal.stacks.top(), "C");
+            assert_eq!(
+                illegal.to_string(),
+                indoc! {"
+                    line 6: move 4 from 1 to 2: cannot move 4 crates from stack 1 which only has 3
+
+                    [C]    
+                    [A]    
+                    [B]    
+                     1   2 "}
+            );
+        }
+
+        // Neither crane panics when running the procedure
+        assert!(super::rearrange(input, &mut CrateMover9000).is_err());
+        assert!(super::rearrange(input, &mut CrateMover9001).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_stack() -> Result<()> {
+        let input = "[A]\n 1 \n\nmove 1 from 1 to 3";
+        let illegal = super::validate(input, CrateMover9001)?.unwrap();
+
+        assert_eq!(
+            illegal.reason,
+            "to stack 3 does not exist, there are only 1 stacks"
+        );
+
+        let input = "[A]\n 1 \n\nmove 1 from 0 to 1";
+        assert!(super::validate(input, CrateMover9001).is_err());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_optimize {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{CrateMover9000, CrateMover9001};
+
+    #[test]
+    fn test_optimize_example() -> Result<()> {
+        let input = super::tests_example::INPUT;
+        let stacks = super::parse_header(input)?;
+        let instructions = super::parse_instructions(input)?;
+
+        // Every move in the example matters, whichever crane is used
+        let optimized = super::optimize(&stacks, &instructions, &CrateMover9000)?;
+        assert_eq!(optimized.len(), 4);
+        assert_eq!(
+            super::simulate(&stacks, &optimized, &CrateMover9000)?,
+            super::simulate(&stacks, &instructions, &CrateMover9000)?
+        );
+
+        let optimized = super::optimize(&stacks, &instructions, &CrateMover9001)?;
+        assert_eq!(optimized.len(), 4);
+        assert_eq!(
+            super::simulate(&stacks, &optimized, &CrateMover9001)?,
+            super::simulate(&stacks, &instructions, &CrateMover9001)?
+        );
+
+        let optimized = super::optimize(&stacks, &instructions, &CrateMover9000)?;
+        let rendered = super::render_input(&stacks, &optimized);
+        assert_eq!(super::rearrange(&rendered, &mut CrateMover9000)?, "CMZ");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_optimize_redundant_moves() -> Result<()> {
+        let input = indoc! {"
+            [A]    
+            [B] [C]
+             1   2 
+
+            move 1 from 1 to 2
+            move 1 from 2 to 1
+            move 1 from 1 to 2
+            move 1 from 1 to 2
+            move 2 from 2 to 1
+            move 2 from 1 to 2
+        "};
+        let stacks = super::parse_header(input)?;
+        let instructions = super::parse_instructions(input)?;
+
+        let optimized = super::optimize(&stacks, &instructions, &CrateMover9000)?;
+
+        assert_eq!(
+            super::render_input(&stacks, &optimized),
+            indoc! {"
+                [A]    
+                [B] [C]
+                 1   2 
+
+                move 2 from 1 to 2"}
+        );
+
+        Ok(())
+    }
 }
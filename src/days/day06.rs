@@ -1,39 +1,362 @@
-use anyhow::Result;
+use std::{
+    collections::HashMap,
+    io::{BufReader, Read},
+    mem,
+};
 
-fn find_packet(input: &str, len: usize) -> Result<usize> {
-    let chars = input.chars().collect::<Vec<char>>();
-
-    chars
-        .windows(len)
-        .enumerate()
-        .find(|(_, window)| {
-            for a in 0..window.len() {
-                for b in 0..window.len() {
-                    if a == b {
-                        continue;
-                    }
-
-                    if window[a] == window[b] {
-                        return false;
-                    }
-                }
+use anyhow::{bail, Result};
+
+/// Detects the first window of `len` distinct bytes (or characters) in a stream, one at a time,
+/// in constant time per item.
+#[derive(Debug, Clone)]
+struct MarkerDetector {
+    len: usize,
+
+    // For each byte value, the position just after it was last seen (or 0 if never seen)
+    last_seen: [usize; 256],
+
+    // The same for characters outside ASCII, which only come up when detecting by character
+    last_seen_wide: HashMap<char, usize>,
+
+    // Position of the first byte of the current run of distinct bytes
+    start: usize,
+
+    // Number of bytes seen so far
+    position: usize,
+}
+
+impl MarkerDetector {
+    fn new(len: usize) -> Self {
+        MarkerDetector {
+            len,
+            last_seen: [0; 256],
+            last_seen_wide: HashMap::new(),
+            start: 0,
+            position: 0,
+        }
+    }
+
+    /// Feeds the next byte of the stream, returning true if it completes a marker.
+    fn push(&mut self, byte: u8) -> bool {
+        let seen = mem::replace(&mut self.last_seen[byte as usize], self.position + 1);
+
+        self.advance(seen)
+    }
+
+    /// Like [`push`](Self::push), but counts whole characters, so that positions are in
+    /// characters rather than bytes. A detector should only be fed one way or the other.
+    fn push_char(&mut self, c: char) -> bool {
+        if c.is_ascii() {
+            return self.push(c as u8);
+        }
+
+        let seen = self
+            .last_seen_wide
+            .insert(c, self.position + 1)
+            .unwrap_or(0);
+
+        self.advance(seen)
+    }
+
+    /// Moves past the next item, given the position just after its previous occurrence.
+    fn advance(&mut self, seen: usize) -> bool {
+        // A repeated item means the run has to start after its previous occurrence
+        self.start = self.start.max(seen);
+        self.position += 1;
+
+        self.position - self.start >= self.len
+    }
+
+    fn position(&self) -> usize {
+        self.position
+    }
+
+    /// Forgets everything seen so far, so the next marker can't overlap any earlier bytes.
+    fn reset(&mut self) {
+        self.start = self.position;
+    }
+}
+
+/// Iterates over the position after every window of `len` distinct bytes, including windows that
+/// overlap each other.
+struct Markers<I> {
+    bytes: I,
+    detector: MarkerDetector,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Markers<I> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<usize> {
+        for byte in self.bytes.by_ref() {
+            if self.detector.push(byte) {
+                return Some(self.detector.position());
+            }
+        }
+
+        None
+    }
+}
+
+fn markers<I: IntoIterator<Item = u8>>(bytes: I, len: usize) -> Result<Markers<I::IntoIter>> {
+    check_marker_len(len)?;
+
+    Ok(Markers {
+        bytes: bytes.into_iter(),
+        detector: MarkerDetector::new(len),
+    })
+}
+
+const PACKET_LEN: usize = 4;
+const MESSAGE_LEN: usize = 14;
+
+#[derive(Debug, PartialEq, Eq)]
+struct StartMarkers {
+    packet: Option<usize>,
+    message: Option<usize>,
+}
+
+/// Finds both the start-of-packet and start-of-message markers in a single pass.
+fn find_start_markers<I: IntoIterator<Item = u8>>(bytes: I) -> StartMarkers {
+    let mut packet = MarkerDetector::new(PACKET_LEN);
+    let mut message = MarkerDetector::new(MESSAGE_LEN);
+    let mut found = StartMarkers {
+        packet: None,
+        message: None,
+    };
+
+    for byte in bytes {
+        if packet.push(byte) && found.packet.is_none() {
+            found.packet = Some(packet.position());
+        }
+
+        if message.push(byte) {
+            found.message = Some(message.position());
+            break;
+        }
+    }
+
+    found
+}
+
+/// Splits a stream into messages. Each message is the payload following a start-of-message
+/// marker, running up to the next marker or the end of the stream. Markers never overlap, so a
+/// new one only starts after the previous marker ends. Anything before the first marker is
+/// skipped.
+struct Frames<I> {
+    bytes: I,
+    detector: MarkerDetector,
+    started: bool,
+    finished: bool,
+    buffer: Vec<u8>,
+}
+
+impl<I: Iterator<Item = u8>> Iterator for Frames<I> {
+    type Item = Vec<u8>;
+
+    fn next(&mut self) -> Option<Vec<u8>> {
+        if self.finished {
+            return None;
+        }
+
+        for byte in self.bytes.by_ref() {
+            self.buffer.push(byte);
+
+            if !self.detector.push(byte) {
+                continue;
+            }
+
+            self.detector.reset();
+
+            if !self.started {
+                self.started = true;
+                self.buffer.clear();
+                continue;
             }
 
-            return true;
+            // Drop the marker itself from the end of the payload
+            self.buffer.truncate(self.buffer.len() - MESSAGE_LEN);
+
+            return Some(std::mem::take(&mut self.buffer));
+        }
+
+        self.finished = true;
+
+        if self.started {
+            Some(std::mem::take(&mut self.buffer))
+        } else {
+            None
+        }
+    }
+}
+
+fn frames<I: IntoIterator<Item = u8>>(bytes: I) -> Frames<I::IntoIter> {
+    Frames {
+        bytes: bytes.into_iter(),
+        detector: MarkerDetector::new(MESSAGE_LEN),
+        started: false,
+        finished: false,
+        buffer: Vec::new(),
+    }
+}
+
+/// Every window is trivially free of repeats when it's empty, so a zero-length marker is an error
+/// rather than a match at the first byte.
+fn check_marker_len(len: usize) -> Result<()> {
+    if len == 0 {
+        bail!("Marker length must be at least 1");
+    }
+
+    Ok(())
+}
+
+/// Returns the number of bytes read up to and including the first marker of `len` distinct
+/// bytes.
+fn find_marker<I: IntoIterator<Item = u8>>(bytes: I, len: usize) -> Result<Option<usize>> {
+    check_marker_len(len)?;
+
+    let mut detector = MarkerDetector::new(len);
+
+    for byte in bytes {
+        if detector.push(byte) {
+            return Ok(Some(detector.position()));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Like [`find_marker`], but reads the stream lazily so it never needs to be held in memory.
+fn find_marker_in<R: Read>(reader: R, len: usize) -> Result<usize> {
+    check_marker_len(len)?;
+
+    let mut detector = MarkerDetector::new(len);
+
+    for byte in BufReader::new(reader).bytes() {
+        if detector.push(byte?) {
+            return Ok(detector.position());
+        }
+    }
+
+    bail!("Failed to find {}-long unique sequence.", len)
+}
+
+/// How the marker moves when a single byte of the stream is changed.
+#[derive(Debug, PartialEq, Eq)]
+struct Sensitivity {
+    position: usize,
+    byte: u8,
+
+    // The marker after deleting this byte
+    deleted: Option<usize>,
+
+    // How many substitutions were tried, and how many of those moved the marker
+    substitutions: usize,
+    moved: usize,
+
+    // The earliest and latest markers found across all substitutions, and how many substitutions
+    // left the stream without a marker at all
+    earliest: Option<usize>,
+    latest: Option<usize>,
+    lost: usize,
+}
+
+/// Mutates each of the first `limit` bytes of the stream, trying every other byte from
+/// `alphabet` in its place as well as deleting it, and records where the marker ends up.
+fn sensitivity(
+    input: &[u8],
+    len: usize,
+    limit: usize,
+    alphabet: &[u8],
+) -> Result<Vec<Sensitivity>> {
+    let baseline = find_marker(input.iter().copied(), len)?;
+
+    (0..limit.min(input.len()))
+        .map(|position| {
+            let (before, after) = (&input[..position], &input[position + 1..]);
+            let byte = input[position];
+
+            let deleted = find_marker(before.iter().chain(after).copied(), len)?;
+
+            let markers = alphabet
+                .iter()
+                .filter(|&&replacement| replacement != byte)
+                .map(|replacement| {
+                    let mutated = before.iter().chain([replacement]).chain(after);
+                    find_marker(mutated.copied(), len)
+                })
+                .collect::<Result<Vec<Option<usize>>>>()?;
+
+            Ok(Sensitivity {
+                position,
+                byte,
+                deleted,
+                substitutions: markers.len(),
+                moved: markers.iter().filter(|&&marker| marker != baseline).count(),
+                earliest: markers.iter().flatten().min().copied(),
+                latest: markers.iter().flatten().max().copied(),
+                lost: markers.iter().filter(|marker| marker.is_none()).count(),
+            })
         })
-        .map(|(i, _)| i + len)
-        .ok_or(anyhow::format_err!(
-            "Failed to find {}-long unique sequence.",
-            len
-        ))
+        .collect()
+}
+
+/// Describes a marker relative to the original one, e.g. `+3`, or `none` if it wasn't found.
+fn describe_shift(baseline: Option<usize>, marker: Option<usize>) -> String {
+    match (baseline, marker) {
+        (_, None) => "none".to_string(),
+        (None, Some(marker)) => marker.to_string(),
+        (Some(baseline), Some(marker)) => format!("{:+}", marker as i64 - baseline as i64),
+    }
+}
+
+fn render_sensitivity(input: &[u8], len: usize, rows: &[Sensitivity]) -> Result<String> {
+    let baseline = find_marker(input.iter().copied(), len)?;
+
+    let mut output = match baseline {
+        Some(marker) => format!("Marker of length {} at {}\n\n", len, marker),
+        None => format!("No marker of length {}\n\n", len),
+    };
+
+    output.push_str("  pos byte  deleted    moved earliest   latest  lost\n");
+
+    for row in rows {
+        output.push_str(&format!(
+            "{:>5} {:>4} {:>8} {:>8} {:>8} {:>8} {:>5}\n",
+            row.position,
+            row.byte as char,
+            describe_shift(baseline, row.deleted),
+            format!("{}/{}", row.moved, row.substitutions),
+            describe_shift(baseline, row.earliest),
+            describe_shift(baseline, row.latest),
+            row.lost
+        ));
+    }
+
+    Ok(output)
+}
+
+/// Finds the first marker in a datastream, counting positions in characters rather than bytes.
+fn find_packet(input: &str, len: usize) -> Result<usize> {
+    check_marker_len(len)?;
+
+    let mut detector = MarkerDetector::new(len);
+
+    for c in input.chars() {
+        if detector.push_char(c) {
+            return Ok(detector.position());
+        }
+    }
+
+    bail!("Failed to find {}-long unique sequence.", len)
 }
 
 fn part1(input: &str) -> Result<usize> {
-    find_packet(input, 4)
+    find_packet(input, PACKET_LEN)
 }
 
 fn part2(input: &str) -> Result<usize> {
-    find_packet(input, 14)
+    find_packet(input, MESSAGE_LEN)
 }
 
 #[cfg(test)]
@@ -65,3 +388,215 @@ mod tests_example {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests_marker {
+    use std::io::{Cursor, Read};
+
+    use anyhow::Result;
+
+    use crate::util::rng::Rng;
+
+    // The original quadratic search, kept to check the detector against
+    fn find_packet_naive(input: &str, len: usize) -> Option<usize> {
+        let chars = input.chars().collect::<Vec<char>>();
+
+        chars
+            .windows(len)
+            .position(|window| {
+                (0..window.len())
+                    .all(|a| (0..window.len()).all(|b| a == b || window[a] != window[b]))
+            })
+            .map(|i| i + len)
+    }
+
+    #[test]
+    fn test_examples() -> Result<()> {
+        let examples = [
+            ("mjqjpqmgbljsphdztnvjfqwrcgsmlb", 7, 19),
+            ("bvwbjplbgvbhsrlpgdmjqwftvncz", 5, 23),
+            ("nppdvjthqldpwncqszvftbrmjlhg", 6, 23),
+            ("nznrnfrfntjfmvfwmzdfjlvtqnbhcprsg", 10, 29),
+            ("zcfzfwzzqfrljwzlrfnpqdbhtmscgvjw", 11, 26),
+        ];
+
+        for (input, packet, message) in examples {
+            assert_eq!(super::part1(input)?, packet);
+            assert_eq!(super::part2(input)?, message);
+            assert_eq!(super::find_marker_in(Cursor::new(input), 14)?, message);
+        }
+
+        assert!(super::find_packet("abcabc", 4).is_err());
+        assert!(super::find_marker_in(Cursor::new("abcabc"), 4).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(super::find_marker("abc".bytes(), 0).is_err());
+        assert!(super::find_marker_in(Cursor::new("abc"), 0).is_err());
+        assert!(super::find_packet("abc", 0).is_err());
+    }
+
+    #[test]
+    fn test_characters() -> Result<()> {
+        // Positions count characters, even where they take several bytes
+        assert_eq!(super::part1("ääbcdefg")?, 5);
+        assert_eq!(super::part1("aé€𝄞")?, 4);
+        assert_eq!(super::find_packet("€€a€b", 3)?, 5);
+        assert!(super::find_packet("éaé", 3).is_err());
+
+        let mut rng = Rng::new(37);
+        let alphabet = ['a', 'b', 'c', 'é', 'ü', '€', '𝄞', 'ß'];
+
+        for _ in 0..500 {
+            let input = (0..rng.range(0, 40))
+                .map(|_| alphabet[rng.range(0, alphabet.len() as u64) as usize])
+                .collect::<String>();
+
+            for len in 1..6 {
+                assert_eq!(
+                    super::find_packet(&input, len).ok(),
+                    find_packet_naive(&input, len),
+                    "{} with length {}",
+                    input,
+                    len
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matches_naive() -> Result<()> {
+        let mut rng = Rng::new(6);
+
+        for _ in 0..500 {
+            let alphabet = rng.range(2, 20) as u8;
+            let input = (0..rng.range(0, 60))
+                .map(|_| (b'a' + rng.range(0, alphabet as u64) as u8) as char)
+                .collect::<String>();
+
+            for len in 1..6 {
+                assert_eq!(
+                    super::find_marker(input.bytes(), len)?,
+                    find_packet_naive(&input, len),
+                    "{} with length {}",
+                    input,
+                    len
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_long_stream() -> Result<()> {
+        // A stream that's all repeats for a long time before the marker shows up
+        let reader = Cursor::new("ab".repeat(1_000_000)).chain(Cursor::new("cdefghijklmnop"));
+
+        assert_eq!(super::find_marker_in(reader, 14)?, 2_000_000 + 12);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_framing {
+    use anyhow::Result;
+
+    use super::StartMarkers;
+
+    #[test]
+    fn test_all_markers() -> Result<()> {
+        let positions = super::markers("aabcaab".bytes(), 3)?.collect::<Vec<usize>>();
+
+        assert_eq!(positions, vec![4, 5]);
+        assert_eq!(super::markers("aaaa".bytes(), 2)?.count(), 0);
+        assert!(super::markers("aaaa".bytes(), 0).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_start_markers() {
+        assert_eq!(
+            super::find_start_markers("mjqjpqmgbljsphdztnvjfqwrcgsmlb".bytes()),
+            StartMarkers {
+                packet: Some(7),
+                message: Some(19)
+            }
+        );
+        assert_eq!(
+            super::find_start_markers("abcdabcd".bytes()),
+            StartMarkers {
+                packet: Some(4),
+                message: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_frames() {
+        let marker = "abcdefghijklmn";
+        let stream = format!("aa{}aaaa{}{}world", marker, marker, marker);
+
+        let frames = super::frames(stream.bytes())
+            .map(|frame| String::from_utf8(frame).unwrap())
+            .collect::<Vec<String>>();
+
+        assert_eq!(frames, vec!["aaaa", "", "world"]);
+        assert_eq!(super::frames("no marker here".bytes()).count(), 0);
+    }
+}
+
+#[cfg(test)]
+mod tests_sensitivity {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    #[test]
+    fn test_sensitivity() -> Result<()> {
+        let input = b"aabcd";
+        let rows = super::sensitivity(input, 3, 3, b"abcd")?;
+
+        // Deleting the first 'a', or changing it to a 'c' or 'd', moves the marker up by one
+        assert_eq!(rows[0].deleted, Some(3));
+        assert_eq!((rows[0].moved, rows[0].substitutions), (2, 3));
+        assert_eq!(rows[0].earliest, Some(3));
+
+        // Changing the 'b' to an 'a' pushes the marker back, and to a 'c' loses it entirely
+        assert_eq!(rows[2].latest, Some(5));
+        assert_eq!(rows[2].lost, 1);
+
+        assert_eq!(
+            super::render_sensitivity(input, 3, &rows)?,
+            indoc! {"
+                Marker of length 3 at 4
+
+                  pos byte  deleted    moved earliest   latest  lost
+                    0    a       -1      2/3       -1       +0     0
+                    1    a       -1      3/3       -1       +1     0
+                    2    b       +0      2/3       +0       +1     1
+            "}
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_lost_marker() -> Result<()> {
+        let rows = super::sensitivity(b"abc", 3, 10, b"abc")?;
+
+        assert_eq!(rows.len(), 3);
+        assert!(rows
+            .iter()
+            .all(|row| row.deleted.is_none() && row.lost == 2));
+        assert!(super::sensitivity(b"abc", 0, 10, b"abc").is_err());
+
+        Ok(())
+    }
+}
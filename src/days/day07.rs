@@ -1,4 +1,11 @@
-use std::collections::{HashMap, VecDeque};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    fs,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 use anyhow::{bail, Context, Result};
 
@@ -6,6 +13,13 @@ use anyhow::{bail, Context, Result};
 enum Command {
     Ls(Vec<LsEntry>),
     Cd(String),
+    /// `pwd`, along with the directory it printed if the transcript includes it
+    Pwd(Option<String>),
+    Mkdir(String),
+    /// `touch <size> <name>` creates a file of the given size, replacing any file already there
+    Touch(usize, String),
+    Rm(String),
+    Mv(String, String),
 }
 
 #[derive(Debug)]
@@ -14,14 +28,21 @@ enum LsEntry {
     Dir(String),
 }
 
+impl LsEntry {
+    fn name(&self) -> &str {
+        match self {
+            LsEntry::File(name, _) | LsEntry::Dir(name) => name,
+        }
+    }
+}
+
 fn parse_commands(input: &str) -> Result<Vec<Command>> {
     Ok(input
         .split("$ ")
         .filter(|exec| !exec.is_empty())
         .map(|exec| {
-            println!("{}", exec);
-
-            let (command, response) = exec.split_once("\n").context("Failed to parse command.")?;
+            // The last command may not have a trailing newline once the input has been trimmed
+            let (command, response) = exec.split_once("\n").unwrap_or((exec, ""));
 
             let args = command.split(" ").collect::<Vec<&str>>();
 
@@ -52,12 +73,41 @@ fn parse_commands(input: &str) -> Result<Vec<Command>> {
 
                     Ok(Command::Cd(dir.to_string()))
                 }
-                _ => bail!("Unknown command: {}", args[0]),
+                "pwd" => {
+                    let printed = response.lines().find(|line| !line.is_empty());
+
+                    Ok(Command::Pwd(printed.map(|line| line.to_string())))
+                }
+                _ => {
+                    if !response.trim().is_empty() {
+                        bail!("Unexpected output for command: {}", command);
+                    }
+
+                    parse_silent_command(&args)
+                }
             }
         })
         .collect::<Result<Vec<Command>>>()?)
 }
 
+/// Parses the commands that don't print anything.
+fn parse_silent_command(args: &[&str]) -> Result<Command> {
+    match args {
+        ["mkdir", path] => Ok(Command::Mkdir(path.to_string())),
+        ["touch", size, path] => Ok(Command::Touch(
+            size.parse()
+                .with_context(|| format!("Invalid size for touch: {}", size))?,
+            path.to_string(),
+        )),
+        ["rm", path] => Ok(Command::Rm(path.to_string())),
+        ["mv", from, to] => Ok(Command::Mv(from.to_string(), to.to_string())),
+        ["mkdir" | "touch" | "rm" | "mv", ..] => {
+            bail!("Wrong number of arguments for {}", args[0])
+        }
+        _ => bail!("Unknown command: {}", args[0]),
+    }
+}
+
 #[derive(Debug, Clone)]
 struct File {
     name: String,
@@ -68,6 +118,9 @@ struct File {
 struct FileTree {
     files: Vec<File>,
     directories: HashMap<String, Box<FileTree>>,
+
+    // Total size of everything below this directory, kept up to date on every insert
+    size: usize,
 }
 
 impl FileTree {
@@ -75,163 +128,2587 @@ impl FileTree {
         FileTree {
             files: Vec::new(),
             directories: HashMap::new(),
+            size: 0,
         }
     }
 
     fn size(&self) -> usize {
-        let file_size: usize = self.files.iter().map(|file| file.size).sum();
-        let dir_size: usize = self.directories.iter().map(|(_, tree)| tree.size()).sum();
-        let total_size = file_size + dir_size;
+        self.size
+    }
 
-        return file_size + dir_size;
+    fn subdir(&mut self, name: &str) -> &mut FileTree {
+        self.directories
+            .entry(name.to_string())
+            .or_insert_with(|| Box::new(FileTree::new()))
     }
-}
 
-fn build_file_tree(commands: Vec<Command>) -> FileTree {
-    let mut tree = FileTree::new();
+    /// Looks up a file or directory directly inside this one.
+    fn child(&self, name: &str) -> Option<Node<'_>> {
+        match self.directories.get(name) {
+            Some(dir) => Some(Node::Dir(dir)),
+            None => self
+                .files
+                .iter()
+                .find(|file| file.name == name)
+                .map(Node::File),
+        }
+    }
 
-    let mut pwd: Vec<String> = Vec::new();
-    for cmd in commands {
-        match cmd {
-            Command::Cd(path) => match path.as_str() {
-                ".." => {
-                    pwd.pop();
-                }
-                "/" => {
-                    pwd.clear();
-                }
-                _ => {
-                    pwd.push(path.to_string());
-                }
-            },
-            Command::Ls(entries) => {
-                let mut curr = &mut tree;
+    fn add_file(&mut self, file: File) {
+        self.size += file.size;
+        self.files.push(file);
+    }
 
-                for part in &pwd {
-                    let node = curr
-                        .directories
-                        .entry(part.to_string())
-                        .or_insert_with(|| Box::new(FileTree::new()));
+    /// Removes a file or directory directly inside this one, returning the size that was freed.
+    fn remove_child(&mut self, name: &str) -> Option<usize> {
+        self.take_child(name).map(|entry| entry.size())
+    }
 
-                    curr = node.as_mut();
-                }
+    /// Removes a file or directory directly inside this one and hands it back.
+    fn take_child(&mut self, name: &str) -> Option<Entry> {
+        let taken = match self.directories.remove(name) {
+            Some(dir) => Entry::Dir(dir),
+            None => {
+                let i = self.files.iter().position(|file| file.name == name)?;
+                Entry::File(self.files.remove(i))
+            }
+        };
 
-                entries.iter().for_each(|entry| match entry {
-                    LsEntry::Dir(name) => {
-                        curr.directories
-                            .insert(name.to_string(), Box::new(FileTree::new()));
-                    }
-                    LsEntry::File(name, size) => curr.files.push(File {
-                        name: name.to_string(),
-                        size: *size,
-                    }),
-                })
+        self.size -= taken.size();
+
+        Some(taken)
+    }
+
+    /// Adds a file or directory directly inside this one under `name`, replacing anything that
+    /// was there before.
+    fn put_child(&mut self, name: &str, entry: Entry) {
+        self.remove_child(name);
+        self.size += entry.size();
+
+        match entry {
+            Entry::File(file) => self.files.push(File {
+                name: name.to_string(),
+                size: file.size,
+            }),
+            Entry::Dir(dir) => {
+                self.directories.insert(name.to_string(), dir);
             }
         }
     }
 
-    tree
+    /// Runs `f` on the directory at `path`, creating any missing directories along the way, and
+    /// then updates the sizes of every directory above it to match.
+    fn with_dir<R>(&mut self, path: &[String], f: impl FnOnce(&mut FileTree) -> R) -> R {
+        let Some((first, rest)) = path.split_first() else {
+            return f(self);
+        };
+
+        let child = self.subdir(first);
+        let before = child.size;
+        let result = child.with_dir(rest, f);
+        let after = child.size;
+
+        self.size = self.size + after - before;
+
+        result
+    }
+
+    /// Adds a file to the directory at `path`, creating any missing directories along the way.
+    fn insert_file(&mut self, path: &[String], file: File) {
+        self.with_dir(path, |dir| dir.add_file(file));
+    }
+
+    /// Adds an empty directory inside the directory at `path`, if there isn't one already.
+    fn insert_dir(&mut self, path: &[String], name: &str) {
+        self.with_dir(path, |dir| {
+            dir.subdir(name);
+        });
+    }
+
+    fn get(&self, path: &[String]) -> Option<Node<'_>> {
+        let Some((last, parents)) = path.split_last() else {
+            return Some(Node::Dir(self));
+        };
+
+        let mut curr = self;
+        for part in parents {
+            curr = curr.directories.get(part)?;
+        }
+
+        curr.child(last)
+    }
+
+    /// Removes the file or directory at `path`, returning the size that was freed.
+    fn remove(&mut self, path: &[String]) -> Option<usize> {
+        self.take(path).map(|entry| entry.size())
+    }
+
+    /// Removes the file or directory at `path` and hands it back.
+    fn take(&mut self, path: &[String]) -> Option<Entry> {
+        let (first, rest) = path.split_first()?;
+
+        if rest.is_empty() {
+            return self.take_child(first);
+        }
+
+        let taken = self.directories.get_mut(first)?.take(rest)?;
+        self.size -= taken.size();
+
+        Some(taken)
+    }
 }
 
-fn part1(input: &str) -> Result<usize> {
-    let commands = parse_commands(input)?;
-    let tree = build_file_tree(commands);
+/// A file or directory that has been taken out of a tree, so it can be put back elsewhere.
+#[derive(Debug)]
+enum Entry {
+    File(File),
+    Dir(Box<FileTree>),
+}
 
-    let mut total = 0;
-    let mut queue = VecDeque::new();
-    queue.push_back(&tree);
+impl Entry {
+    fn size(&self) -> usize {
+        match self {
+            Entry::File(file) => file.size,
+            Entry::Dir(dir) => dir.size(),
+        }
+    }
+}
 
-    while let Some(node) = queue.pop_front() {
-        for dir in node.directories.values() {
-            queue.push_back(&*dir);
+fn build_file_tree(commands: Vec<Command>) -> Result<FileTree> {
+    let (tree, _) = build_checked(commands, Mode::Lenient)?;
+
+    Ok(tree)
+}
+
+/// A node in the file tree, as returned by path lookups.
+#[derive(Debug, Clone, Copy)]
+enum Node<'a> {
+    File(&'a File),
+    Dir(&'a FileTree),
+}
+
+impl Node<'_> {
+    fn size(&self) -> usize {
+        match self {
+            Node::File(file) => file.size,
+            Node::Dir(tree) => tree.size(),
         }
+    }
 
-        let size = node.size();
-        if size <= 100_000 {
-            total += size;
+    fn is_dir(&self) -> bool {
+        matches!(self, Node::Dir(_))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct Stat {
+    path: String,
+    is_dir: bool,
+    size: usize,
+}
+
+/// Splits a path into its components, resolving `.` and `..` relative to `base`. Absolute paths
+/// ignore `base` entirely.
+fn resolve_path(base: &[String], path: &str) -> Result<Vec<String>> {
+    let mut resolved = if path.starts_with('/') {
+        Vec::new()
+    } else {
+        base.to_vec()
+    };
+
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                resolved
+                    .pop()
+                    .with_context(|| format!("Path goes above root: {}", path))?;
+            }
+            _ => resolved.push(part.to_string()),
         }
     }
 
-    Ok(total)
+    Ok(resolved)
 }
 
-const DISK_SIZE: usize = 70_000_000;
-const UPDATE_SIZE: usize = 30_000_000;
-const REQ_SIZE: usize = DISK_SIZE - UPDATE_SIZE;
+fn format_path(parts: &[String]) -> String {
+    format!("/{}", parts.join("/"))
+}
 
-fn part2(input: &str) -> Result<usize> {
-    let commands = parse_commands(input)?;
-    let tree = build_file_tree(commands);
+/// A file tree with a working directory, so that nodes can be looked up by absolute or relative
+/// paths.
+#[derive(Debug, Clone)]
+struct Vfs {
+    root: FileTree,
+    cwd: Vec<String>,
+}
+
+impl Vfs {
+    fn new(root: FileTree) -> Self {
+        Vfs {
+            root,
+            cwd: Vec::new(),
+        }
+    }
 
-    let curr_size = tree.size();
-    let min_size_remove = curr_size - REQ_SIZE;
+    fn parse(input: &str) -> Result<Self> {
+        Ok(Vfs::new(build_file_tree(parse_commands(input)?)?))
+    }
 
-    let mut queue = VecDeque::new();
-    queue.push_back(&tree);
+    fn cwd(&self) -> String {
+        format_path(&self.cwd)
+    }
+
+    fn resolve(&self, path: &str) -> Result<Vec<String>> {
+        resolve_path(&self.cwd, path)
+    }
 
-    let mut curr_remove_size = DISK_SIZE;
+    fn cd(&mut self, path: &str) -> Result<()> {
+        let resolved = self.resolve(path)?;
 
-    while let Some(node) = queue.pop_front() {
-        for dir in node.directories.values() {
-            queue.push_back(&*dir);
+        match self.lookup(&resolved)? {
+            Node::Dir(_) => {
+                self.cwd = resolved;
+                Ok(())
+            }
+            Node::File(_) => bail!("Not a directory: {}", format_path(&resolved)),
         }
+    }
 
-        let size = node.size();
-        if size >= min_size_remove && size < curr_remove_size {
-            curr_remove_size = size;
+    fn lookup(&self, parts: &[String]) -> Result<Node<'_>> {
+        let mut curr = &self.root;
+
+        for (i, part) in parts.iter().enumerate() {
+            if let Some(dir) = curr.directories.get(part) {
+                curr = dir;
+                continue;
+            }
+
+            // Files can only be the very last part of a path
+            let file = curr.files.iter().find(|file| &file.name == part);
+            match file {
+                Some(file) if i == parts.len() - 1 => return Ok(Node::File(file)),
+                _ => bail!("No such file or directory: {}", format_path(&parts[..=i])),
+            }
+        }
+
+        Ok(Node::Dir(curr))
+    }
+
+    fn get(&self, path: &str) -> Result<Node<'_>> {
+        self.lookup(&self.resolve(path)?)
+    }
+
+    fn stat(&self, path: &str) -> Result<Stat> {
+        let resolved = self.resolve(path)?;
+        let node = self.lookup(&resolved)?;
+
+        Ok(Stat {
+            path: format_path(&resolved),
+            is_dir: node.is_dir(),
+            size: node.size(),
+        })
+    }
+
+    fn size(&self, path: &str) -> Result<usize> {
+        Ok(self.get(path)?.size())
+    }
+
+    /// Lists the entries of a directory by name, or just the file itself if given a file.
+    fn list(&self, path: &str) -> Result<Vec<(String, Node<'_>)>> {
+        let resolved = self.resolve(path)?;
+
+        match self.lookup(&resolved)? {
+            Node::Dir(tree) => Ok(children(tree)),
+            file => Ok(vec![(resolved.last().cloned().unwrap_or_default(), file)]),
         }
     }
 
-    Ok(curr_remove_size)
+    /// Deletes a file or directory, returning the size that was freed. The working directory
+    /// and the directories above it can't be removed.
+    fn remove(&mut self, path: &str) -> Result<usize> {
+        let resolved = self.resolve(path)?;
+
+        if self.cwd.starts_with(&resolved) {
+            bail!(
+                "Can't remove the working directory or its parents: {}",
+                format_path(&resolved)
+            );
+        }
+
+        self.root
+            .remove(&resolved)
+            .with_context(|| format!("No such file or directory: {}", format_path(&resolved)))
+    }
+
+    /// Iterates over every node in the tree along with its absolute path, parents before their
+    /// children.
+    fn walk(&self) -> Walk<'_> {
+        Walk {
+            stack: vec![(Vec::new(), Node::Dir(&self.root))],
+        }
+    }
 }
 
-#[cfg(test)]
-mod tests_example {
-    use anyhow::Result;
-    use indoc::indoc;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Fail on the first inconsistency in the transcript
+    Strict,
 
-    use crate::util;
+    /// Report inconsistencies but carry on, merging conflicting listings
+    Lenient,
+}
 
-    const INPUT: &str = indoc! {"
-        $ cd /
-        $ ls
-        dir a
-        14848514 b.txt
-        8504156 c.dat
-        dir d
-        $ cd a
-        $ ls
-        dir e
-        29116 f
-        2557 g
-        62596 h.lst
-        $ cd e
-        $ ls
-        584 i
-        $ cd ..
-        $ cd ..
-        $ cd d
-        $ ls
-        4060174 j
-        8033020 d.log
-        5626152 d.ext
-        7214296 k
-    "};
+#[derive(Debug, PartialEq, Eq)]
+enum Problem {
+    DuplicateEntry(String),
+    ConflictingEntry {
+        path: String,
+        previous: String,
+        listed: String,
+    },
+    UnlistedDirectory(String),
+    AboveRoot,
+    NotFound(String),
+    AlreadyExists(String),
+    RemovesWorkingDirectory(String),
+    MoveIntoItself {
+        from: String,
+        to: String,
+    },
+    WrongWorkingDirectory {
+        printed: String,
+        actual: String,
+    },
+}
 
-    #[test]
-    fn test_part1() -> Result<()> {
-        let result = super::part1(util::format_input(INPUT))?;
+/// A problem found in a transcript, along with the (1-based) index of the command it came from.
+#[derive(Debug, PartialEq, Eq)]
+struct Diagnostic {
+    command: usize,
+    problem: Problem,
+}
 
-        assert_eq!(result, 95437);
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "command {}: ", self.command)?;
+
+        match &self.problem {
+            Problem::DuplicateEntry(path) => write!(f, "{} is listed more than once", path),
+            Problem::ConflictingEntry {
+                path,
+                previous,
+                listed,
+            } => write!(
+                f,
+                "{} was previously listed as {} but is now {}",
+                path, previous, listed
+            ),
+            Problem::UnlistedDirectory(path) => {
+                write!(f, "cd into {} which was never listed", path)
+            }
+            Problem::AboveRoot => write!(f, "path goes above the root directory"),
+            Problem::NotFound(path) => write!(f, "{} does not exist", path),
+            Problem::AlreadyExists(path) => write!(f, "{} already exists", path),
+            Problem::RemovesWorkingDirectory(path) => {
+                write!(f, "rm {} would remove the working directory", path)
+            }
+            Problem::MoveIntoItself { from, to } => {
+                write!(f, "mv {} into itself at {}", from, to)
+            }
+            Problem::WrongWorkingDirectory { printed, actual } => write!(
+                f,
+                "pwd printed {} but the working directory is {}",
+                printed, actual
+            ),
+        }
+    }
+}
+
+fn describe_node(node: Option<Node>) -> String {
+    match node {
+        Some(Node::Dir(_)) => "a directory".to_string(),
+        Some(Node::File(file)) => format!("a file of size {}", file.size),
+        None => "missing".to_string(),
+    }
+}
+
+fn describe_entry(entry: &LsEntry) -> String {
+    match entry {
+        LsEntry::Dir(_) => "a directory".to_string(),
+        LsEntry::File(_, size) => format!("a file of size {}", size),
+    }
+}
+
+/// Replays a transcript into a file tree, checking that the commands are consistent with each
+/// other. In lenient mode, later listings win over earlier ones, but entries are never dropped
+/// just because a later listing left them out.
+struct Builder {
+    vfs: Vfs,
+    mode: Mode,
+
+    // The command currently being applied, for diagnostics
+    command: usize,
+
+    listed: HashSet<Vec<String>>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Builder {
+    fn new(mode: Mode) -> Self {
+        Builder {
+            vfs: Vfs::new(FileTree::new()),
+            mode,
+            command: 0,
+            listed: HashSet::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    fn report(&mut self, problem: Problem) -> Result<()> {
+        let diagnostic = Diagnostic {
+            command: self.command,
+            problem,
+        };
+
+        if self.mode == Mode::Strict {
+            bail!("Inconsistent transcript: {}", diagnostic);
+        }
+
+        self.diagnostics.push(diagnostic);
 
         Ok(())
     }
 
-    #[test]
-    fn test_part2() -> Result<()> {
-        let result = super::part2(util::format_input(INPUT))?;
+    fn apply(&mut self, command: Command) -> Result<()> {
+        self.command += 1;
 
-        assert_eq!(result, 24933642);
+        match command {
+            Command::Cd(path) => self.cd(&path),
+            Command::Ls(entries) => self.ls(entries),
+            Command::Pwd(printed) => self.pwd(printed),
+            Command::Mkdir(path) => self.mkdir(&path),
+            Command::Touch(size, path) => self.touch(size, &path),
+            Command::Rm(path) => self.rm(&path),
+            Command::Mv(from, to) => self.mv(&from, &to),
+        }
+    }
+
+    /// Resolves a path against the working directory, reporting paths that go above the root.
+    fn resolve(&mut self, path: &str) -> Result<Option<Vec<String>>> {
+        match self.vfs.resolve(path) {
+            Ok(resolved) => Ok(Some(resolved)),
+            Err(_) => {
+                self.report(Problem::AboveRoot)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Makes sure the directory that `path` would be created in exists, creating it if not.
+    fn ensure_parent(&mut self, path: &[String]) -> Result<()> {
+        let Some((_, parent)) = path.split_last() else {
+            return Ok(());
+        };
+
+        if !self.vfs.root.get(parent).is_some_and(|node| node.is_dir()) {
+            self.report(Problem::NotFound(format_path(parent)))?;
+
+            if let Some((name, grandparent)) = parent.split_last() {
+                self.vfs.root.remove(parent);
+                self.vfs.root.insert_dir(grandparent, name);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn pwd(&mut self, printed: Option<String>) -> Result<()> {
+        let actual = self.vfs.cwd();
+
+        match printed {
+            Some(printed) if printed != actual => {
+                self.report(Problem::WrongWorkingDirectory { printed, actual })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn mkdir(&mut self, path: &str) -> Result<()> {
+        let Some(target) = self.resolve(path)? else {
+            return Ok(());
+        };
+
+        if self.vfs.root.get(&target).is_some() {
+            return self.report(Problem::AlreadyExists(format_path(&target)));
+        }
+
+        self.ensure_parent(&target)?;
+        if let Some((name, parent)) = target.split_last() {
+            self.vfs.root.insert_dir(parent, name);
+        }
+
+        Ok(())
+    }
+
+    fn touch(&mut self, size: usize, path: &str) -> Result<()> {
+        let Some(target) = self.resolve(path)? else {
+            return Ok(());
+        };
+
+        let Some((name, parent)) = target.split_last() else {
+            return self.report(Problem::AlreadyExists(format_path(&target)));
+        };
+
+        if self.vfs.root.get(&target).is_some_and(|node| node.is_dir()) {
+            return self.report(Problem::AlreadyExists(format_path(&target)));
+        }
+
+        self.ensure_parent(&target)?;
+        self.vfs.root.remove(&target);
+        self.vfs.root.insert_file(
+            parent,
+            File {
+                name: name.to_string(),
+                size,
+            },
+        );
+
+        Ok(())
+    }
+
+    fn rm(&mut self, path: &str) -> Result<()> {
+        let Some(target) = self.resolve(path)? else {
+            return Ok(());
+        };
+
+        if self.vfs.cwd.starts_with(&target) {
+            return self.report(Problem::RemovesWorkingDirectory(format_path(&target)));
+        }
+
+        if self.vfs.root.remove(&target).is_none() {
+            return self.report(Problem::NotFound(format_path(&target)));
+        }
+
+        self.listed.retain(|dir| !dir.starts_with(&target));
+
+        Ok(())
+    }
+
+    fn mv(&mut self, from: &str, to: &str) -> Result<()> {
+        let (Some(source), Some(mut target)) = (self.resolve(from)?, self.resolve(to)?) else {
+            return Ok(());
+        };
+
+        let Some(node) = self.vfs.root.get(&source) else {
+            return self.report(Problem::NotFound(format_path(&source)));
+        };
+        let moving_dir = node.is_dir();
+
+        // Moving onto a directory moves into it, keeping the name
+        if let (Some(Node::Dir(_)), Some(name)) = (self.vfs.root.get(&target), source.last()) {
+            target.push(name.to_string());
+        }
+
+        if target == source {
+            return Ok(());
+        }
+
+        if source.is_empty() || target.starts_with(&source) {
+            return self.report(Problem::MoveIntoItself {
+                from: format_path(&source),
+                to: format_path(&target),
+            });
+        }
+
+        // Files can be replaced, but directories can't be replaced or replace a file
+        if let Some(existing) = self.vfs.root.get(&target) {
+            if moving_dir || existing.is_dir() {
+                return self.report(Problem::AlreadyExists(format_path(&target)));
+            }
+        }
+
+        self.ensure_parent(&target)?;
+
+        let Some((name, parent)) = target.split_last() else {
+            return Ok(());
+        };
+        if let Some(entry) = self.vfs.root.take(&source) {
+            self.vfs
+                .root
+                .with_dir(parent, |dir| dir.put_child(name, entry));
+        }
+
+        // Anything that was under the old path, including the working directory, follows it
+        let rename = |path: &Vec<String>| -> Vec<String> {
+            match path.strip_prefix(source.as_slice()) {
+                Some(rest) => target.iter().chain(rest).cloned().collect(),
+                None => path.clone(),
+            }
+        };
+        self.listed = self.listed.iter().map(rename).collect();
+        self.vfs.cwd = rename(&self.vfs.cwd);
+
+        Ok(())
+    }
+
+    fn cd(&mut self, path: &str) -> Result<()> {
+        let target = match self.vfs.resolve(path) {
+            Ok(target) => target,
+            Err(_) => {
+                // Like a real shell, going up from the root leaves us at the root
+                self.report(Problem::AboveRoot)?;
+                Vec::new()
+            }
+        };
+
+        if !self.vfs.root.get(&target).is_some_and(|node| node.is_dir()) {
+            self.report(Problem::UnlistedDirectory(format_path(&target)))?;
+
+            if let Some((name, parent)) = target.split_last() {
+                self.vfs.root.remove(&target);
+                self.vfs.root.insert_dir(parent, name);
+            }
+        }
+
+        self.vfs.cwd = target;
+
+        Ok(())
+    }
+
+    fn ls(&mut self, entries: Vec<LsEntry>) -> Result<()> {
+        let dir = self.vfs.cwd.clone();
+        let relisting = !self.listed.insert(dir.clone());
+        let child_path = |name: &str| {
+            let mut path = dir.clone();
+            path.push(name.to_string());
+            format_path(&path)
+        };
+
+        // Work out everything that needs to change up front, so that the directory only has to
+        // be found once rather than once per entry
+        let mut problems = Vec::new();
+        let mut changes = Vec::new();
+        let mut seen = HashSet::new();
+
+        let current = match self.vfs.root.get(&dir) {
+            Some(Node::Dir(tree)) => tree,
+            _ => bail!("Working directory {} does not exist", format_path(&dir)),
+        };
+
+        for entry in entries {
+            if !seen.insert(entry.name().to_string()) {
+                problems.push(Problem::DuplicateEntry(child_path(entry.name())));
+            }
+
+            let existing = current.child(entry.name());
+            let matches = match (existing, &entry) {
+                (Some(Node::Dir(_)), LsEntry::Dir(_)) => true,
+                (Some(Node::File(file)), LsEntry::File(_, size)) => file.size == *size,
+                _ => false,
+            };
+
+            if matches {
+                continue;
+            }
+
+            if existing.is_some() {
+                problems.push(Problem::ConflictingEntry {
+                    path: child_path(entry.name()),
+                    previous: describe_node(existing),
+                    listed: describe_entry(&entry),
+                });
+            }
+
+            changes.push(entry);
+        }
+
+        if relisting {
+            for (name, node) in children(current) {
+                if !seen.contains(&name) {
+                    problems.push(Problem::ConflictingEntry {
+                        path: child_path(&name),
+                        previous: describe_node(Some(node)),
+                        listed: "missing".to_string(),
+                    });
+                }
+            }
+        }
+
+        for problem in problems {
+            self.report(problem)?;
+        }
+
+        self.vfs.root.with_dir(&dir, |tree| {
+            for entry in changes {
+                tree.remove_child(entry.name());
+
+                match entry {
+                    LsEntry::Dir(name) => {
+                        tree.subdir(&name);
+                    }
+                    LsEntry::File(name, size) => tree.add_file(File { name, size }),
+                }
+            }
+        });
+
+        Ok(())
+    }
+}
+
+/// Builds a file tree from a transcript, returning any inconsistencies found along the way. In
+/// strict mode, the first inconsistency is returned as an error instead.
+fn build_checked(commands: Vec<Command>, mode: Mode) -> Result<(FileTree, Vec<Diagnostic>)> {
+    let mut builder = Builder::new(mode);
+
+    for command in commands {
+        builder.apply(command)?;
+    }
+
+    Ok((builder.vfs.root, builder.diagnostics))
+}
+
+/// The entries of a directory, sorted by name.
+fn children(tree: &FileTree) -> Vec<(String, Node<'_>)> {
+    let mut entries = tree
+        .directories
+        .iter()
+        .map(|(name, dir)| (name.to_string(), Node::Dir(dir)))
+        .chain(
+            tree.files
+                .iter()
+                .map(|file| (file.name.to_string(), Node::File(file))),
+        )
+        .collect::<Vec<(String, Node)>>();
+    entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    entries
+}
+
+struct Walk<'a> {
+    stack: Vec<(Vec<String>, Node<'a>)>,
+}
+
+impl<'a> Iterator for Walk<'a> {
+    type Item = (String, Node<'a>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (path, node) = self.stack.pop()?;
+
+        if let Node::Dir(tree) = node {
+            // Push in reverse so that children come out in name order
+            for (name, child) in children(tree).into_iter().rev() {
+                let mut child_path = path.clone();
+                child_path.push(name);
+                self.stack.push((child_path, child));
+            }
+        }
+
+        Some((format_path(&path), node))
+    }
+}
+
+/// Renders the tree in the same indented format the puzzle uses to describe it.
+fn render_tree(vfs: &Vfs) -> String {
+    vfs.walk()
+        .map(|(path, node)| {
+            let depth = path.matches('/').count() - 1;
+            let name = path.rsplit('/').next().filter(|name| !name.is_empty());
+            let indent = "  ".repeat(if name.is_some() { depth + 1 } else { 0 });
+
+            match node {
+                Node::Dir(_) => format!("{}- {} (dir)\n", indent, name.unwrap_or("/")),
+                Node::File(file) => format!(
+                    "{}- {} (file, size={})\n",
+                    indent,
+                    name.unwrap_or_default(),
+                    file.size
+                ),
+            }
+        })
+        .collect()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct ReportRow {
+    path: String,
+    size: usize,
+}
+
+/// Formats a size the way `du -h` does, rounding up to one decimal place below 10 and to a whole
+/// number above.
+fn human_size(size: usize) -> String {
+    let units = ["K", "M", "G", "T"];
+
+    if size < 1024 {
+        return size.to_string();
+    }
+
+    // Rounding up can carry a value into the next unit (1023.9K is 1.0M), so each unit is only
+    // settled on once the rounded value fits in it
+    let mut value = size as f64;
+    for (i, unit) in units.iter().enumerate() {
+        value /= 1024.0;
+
+        let tenths = (value * 10.0).ceil() / 10.0;
+        let rounded = if tenths < 10.0 { tenths } else { value.ceil() };
+
+        if rounded < 1024.0 || i == units.len() - 1 {
+            return if rounded < 10.0 {
+                format!("{:.1}{}", rounded, unit)
+            } else {
+                format!("{}{}", rounded, unit)
+            };
+        }
+    }
+
+    unreachable!()
+}
+
+fn render_rows(rows: &[ReportRow]) -> String {
+    rows.iter()
+        .map(|row| format!("{}\t{}\n", human_size(row.size), row.path))
+        .collect()
+}
+
+/// Every directory and its total size, largest first.
+fn du_rows(vfs: &Vfs) -> Vec<ReportRow> {
+    sorted_rows(vfs, true)
+}
+
+/// The `count` largest files, largest first.
+fn largest_files(vfs: &Vfs, count: usize) -> Vec<ReportRow> {
+    let mut rows = sorted_rows(vfs, false);
+    rows.truncate(count);
+
+    rows
+}
+
+fn sorted_rows(vfs: &Vfs, dirs: bool) -> Vec<ReportRow> {
+    let mut rows = vfs
+        .walk()
+        .filter(|(_, node)| node.is_dir() == dirs)
+        .map(|(path, node)| ReportRow {
+            path,
+            size: node.size(),
+        })
+        .collect::<Vec<ReportRow>>();
+    rows.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)));
+
+    rows
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Report {
+    Tree,
+    Du,
+    Top(usize),
+}
+
+impl FromStr for Report {
+    type Err = anyhow::Error;
+
+    /// Parses a report name as given on the command line: `tree`, `du`, `top` or `top=N`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s.split_once('=') {
+            None if s == "tree" => Ok(Report::Tree),
+            None if s == "du" => Ok(Report::Du),
+            None if s == "top" => Ok(Report::Top(10)),
+            Some(("top", count)) => Ok(Report::Top(
+                count.parse().context("Invalid number of files")?,
+            )),
+            _ => bail!("Unknown report: {} (expected tree, du or top)", s),
+        }
+    }
+}
+
+pub(crate) fn report(input: &str, report: &Report) -> Result<String> {
+    let vfs = Vfs::parse(input)?;
+
+    Ok(match report {
+        Report::Tree => render_tree(&vfs),
+        Report::Du => render_rows(&du_rows(&vfs)),
+        Report::Top(count) => render_rows(&largest_files(&vfs, *count)),
+    })
+}
+
+const SHELL_HELP: &str = "\
+commands:
+  cd <path>               change directory
+  ls [path]               list a directory
+  pwd                     print the working directory
+  du [path]               print the size of every directory below path
+  find [path] -size +N    print everything below path larger than N
+  rm <path>               delete a file or directory
+  df                      print used and free space, and how much the update still needs
+  exit                    leave the shell
+";
+
+/// Runs a single shell command against `vfs`, writing its output to `out`. Returns `false` once
+/// the shell should exit.
+fn shell_command(vfs: &mut Vfs, line: &str, out: &mut impl Write) -> Result<bool> {
+    let args = line.split_whitespace().collect::<Vec<&str>>();
+    let Some((&command, args)) = args.split_first() else {
+        return Ok(true);
+    };
+
+    match (command, args) {
+        ("exit" | "quit", []) => return Ok(false),
+        ("help", []) => write!(out, "{}", SHELL_HELP)?,
+        ("pwd", []) => writeln!(out, "{}", vfs.cwd())?,
+        ("cd", [path]) => vfs.cd(path)?,
+        ("ls", []) | ("ls", [_]) => {
+            for (name, node) in vfs.list(args.first().unwrap_or(&"."))? {
+                match node {
+                    Node::Dir(_) => writeln!(out, "dir {}", name)?,
+                    Node::File(file) => writeln!(out, "{} {}", file.size, name)?,
+                }
+            }
+        }
+        ("du", []) | ("du", [_]) => {
+            let base = vfs.resolve(args.first().unwrap_or(&"."))?;
+            let path = format_path(&base);
+
+            for (child, node) in vfs.walk() {
+                if node.is_dir() && is_below(&child, &path) {
+                    writeln!(out, "{}\t{}", node.size(), child)?;
+                }
+            }
+        }
+        ("find", ["-size", size]) | ("find", [_, "-size", size]) => {
+            let min_size = size
+                .strip_prefix('+')
+                .and_then(|size| size.parse::<usize>().ok())
+                .with_context(|| format!("Invalid size: {} (expected +N)", size))?;
+
+            let path = match args {
+                [path, ..] if args.len() == 3 => *path,
+                _ => ".",
+            };
+            let path = format_path(&vfs.resolve(path)?);
+
+            for (child, node) in vfs.walk() {
+                if node.size() > min_size && is_below(&child, &path) {
+                    let suffix = if node.is_dir() && child != "/" {
+                        "/"
+                    } else {
+                        ""
+                    };
+                    writeln!(out, "{}\t{}{}", node.size(), child, suffix)?;
+                }
+            }
+        }
+        ("rm", [path]) => {
+            let freed = vfs.remove(path)?;
+            writeln!(out, "freed {}", freed)?;
+        }
+        ("df", []) => {
+            let used = vfs.size("/")?;
+            writeln!(out, "used {}", used)?;
+            writeln!(out, "free {}", DISK_SIZE.saturating_sub(used))?;
+            writeln!(out, "needed {}", used.saturating_sub(REQ_SIZE))?;
+        }
+        _ => bail!(
+            "Unknown command or wrong arguments: {} (try help)",
+            line.trim()
+        ),
+    }
+
+    Ok(true)
+}
+
+/// Whether `path` is `base` itself or somewhere below it.
+fn is_below(path: &str, base: &str) -> bool {
+    base == "/"
+        || path == base
+        || path
+            .strip_prefix(base)
+            .is_some_and(|rest| rest.starts_with('/'))
+}
+
+/// Loads a transcript and runs an interactive shell over it, reading commands from `input` until
+/// it runs out or `exit` is given. Errors from individual commands are printed rather than
+/// returned, so that a typo doesn't end the session.
+pub(crate) fn shell(transcript: &str, input: impl BufRead, mut out: impl Write) -> Result<()> {
+    let mut vfs = Vfs::parse(transcript)?;
+
+    write!(out, "{}$ ", vfs.cwd())?;
+    out.flush()?;
+
+    for line in input.lines() {
+        match shell_command(&mut vfs, &line?, &mut out) {
+            Ok(true) => {}
+            Ok(false) => return Ok(()),
+            Err(err) => writeln!(out, "error: {}", err)?,
+        }
+
+        write!(out, "{}$ ", vfs.cwd())?;
+        out.flush()?;
+    }
+
+    writeln!(out)?;
+
+    Ok(())
+}
+
+/// The order in which [`disk_transcript`] visits directories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Traversal {
+    /// Descends into each subdirectory right after listing its parent, backing out with `cd ..`.
+    DepthFirst,
+    /// Lists a whole level before moving on to the next, walking there from `/` each time.
+    BreadthFirst,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TranscriptOptions {
+    traversal: Traversal,
+    /// Lists entries and visits subdirectories in reverse name order.
+    reverse: bool,
+    /// Lists every directory twice, going back to `/` and walking down again in between. The
+    /// extra commands shouldn't change the resulting tree.
+    redundant: bool,
+}
+
+/// A directory read from disk, kept separate from [`FileTree`] so the two can be compared.
+#[derive(Debug, Default)]
+struct DiskDir {
+    files: Vec<(String, usize)>,
+    dirs: Vec<(String, DiskDir)>,
+}
+
+impl DiskDir {
+    /// Reads a directory recursively, sorting entries by name. Symlinks and names that can't be
+    /// written in the puzzle format are left out and returned in `skipped`.
+    fn read(path: &Path, skipped: &mut Vec<PathBuf>) -> Result<Self> {
+        let mut dir = DiskDir::default();
+
+        let entries = fs::read_dir(path)
+            .with_context(|| format!("Failed to read directory: {}", path.display()))?;
+
+        for entry in entries {
+            let entry = entry?;
+            let file_type = entry.file_type()?;
+
+            let name = match entry.file_name().into_string() {
+                Ok(name) if !name.contains(char::is_whitespace) => name,
+                _ => {
+                    skipped.push(entry.path());
+                    continue;
+                }
+            };
+
+            if file_type.is_dir() {
+                dir.dirs
+                    .push((name, DiskDir::read(&entry.path(), skipped)?));
+            } else if file_type.is_file() {
+                dir.files.push((name, entry.metadata()?.len() as usize));
+            } else {
+                skipped.push(entry.path());
+            }
+        }
+
+        dir.files.sort();
+        dir.dirs.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Ok(dir)
+    }
+
+    /// Records the size of this directory and every directory below it, keyed by path.
+    fn sizes(&self, path: &mut Vec<String>, sizes: &mut HashMap<String, usize>) -> usize {
+        let mut size = self.files.iter().map(|(_, size)| size).sum::<usize>();
+
+        for (name, dir) in &self.dirs {
+            path.push(name.to_string());
+            size += dir.sizes(path, sizes);
+            path.pop();
+        }
+
+        sizes.insert(format_path(path), size);
+
+        size
+    }
+}
+
+/// Writes transcripts for a [`DiskDir`], one command at a time.
+struct TranscriptWriter {
+    options: TranscriptOptions,
+    lines: Vec<String>,
+}
+
+impl TranscriptWriter {
+    fn subdirs<'a>(&self, dir: &'a DiskDir) -> Vec<&'a (String, DiskDir)> {
+        let mut dirs = dir.dirs.iter().collect::<Vec<_>>();
+        if self.options.reverse {
+            dirs.reverse();
+        }
+
+        dirs
+    }
+
+    fn cd_from_root(&mut self, path: &[String]) {
+        self.lines.push("$ cd /".to_string());
+        for part in path {
+            self.lines.push(format!("$ cd {}", part));
+        }
+    }
+
+    fn ls(&mut self, dir: &DiskDir, path: &[String]) {
+        let mut entries = dir
+            .dirs
+            .iter()
+            .map(|(name, _)| (name, format!("dir {}", name)))
+            .chain(
+                dir.files
+                    .iter()
+                    .map(|(name, size)| (name, format!("{} {}", size, name))),
+            )
+            .collect::<Vec<_>>();
+        entries.sort();
+        if self.options.reverse {
+            entries.reverse();
+        }
+
+        let listing = entries
+            .into_iter()
+            .map(|(_, line)| line)
+            .collect::<Vec<_>>();
+
+        self.lines.push("$ ls".to_string());
+        self.lines.extend(listing.iter().cloned());
+
+        if self.options.redundant {
+            self.cd_from_root(path);
+            self.lines.push("$ ls".to_string());
+            self.lines.extend(listing);
+        }
+    }
+
+    fn depth_first(&mut self, dir: &DiskDir, path: &mut Vec<String>) {
+        self.ls(dir, path);
+
+        for (name, subdir) in self.subdirs(dir) {
+            self.lines.push(format!("$ cd {}", name));
+            path.push(name.to_string());
+            self.depth_first(subdir, path);
+            path.pop();
+            self.lines.push("$ cd ..".to_string());
+        }
+    }
+
+    fn breadth_first(&mut self, root: &DiskDir) {
+        let mut queue = VecDeque::new();
+        queue.push_back((Vec::new(), root));
+
+        while let Some((path, dir)) = queue.pop_front() {
+            if !path.is_empty() {
+                self.cd_from_root(&path);
+            }
+            self.ls(dir, &path);
+
+            for (name, subdir) in self.subdirs(dir) {
+                let mut subpath = path.clone();
+                subpath.push(name.to_string());
+                queue.push_back((subpath, subdir));
+            }
+        }
+    }
+}
+
+/// A transcript generated from a directory on disk, along with what it should produce.
+#[derive(Debug)]
+struct DiskTranscript {
+    transcript: String,
+    /// The on-disk size of every directory, keyed by its absolute path in the transcript.
+    sizes: HashMap<String, usize>,
+    /// Entries that were left out of the transcript.
+    skipped: Vec<PathBuf>,
+}
+
+/// Walks a directory on disk and writes a transcript that would produce the same tree, with `/`
+/// standing in for `root`.
+fn disk_transcript(root: &Path, options: TranscriptOptions) -> Result<DiskTranscript> {
+    let mut skipped = Vec::new();
+    let dir = DiskDir::read(root, &mut skipped)?;
+
+    let mut writer = TranscriptWriter {
+        options,
+        lines: vec!["$ cd /".to_string()],
+    };
+    match options.traversal {
+        Traversal::DepthFirst => writer.depth_first(&dir, &mut Vec::new()),
+        Traversal::BreadthFirst => writer.breadth_first(&dir),
+    }
+
+    let mut sizes = HashMap::new();
+    dir.sizes(&mut Vec::new(), &mut sizes);
+
+    Ok(DiskTranscript {
+        transcript: writer.lines.join("\n") + "\n",
+        sizes,
+        skipped,
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct SizeMismatch {
+    path: String,
+    disk: usize,
+    tree: Option<usize>,
+}
+
+/// Generates a transcript for `root`, builds a tree from it in strict mode and compares the size
+/// of every directory against the one on disk.
+fn check_against_disk(root: &Path, options: TranscriptOptions) -> Result<Vec<SizeMismatch>> {
+    let generated = disk_transcript(root, options)?;
+
+    let commands = parse_commands(&generated.transcript)?;
+    let (tree, _) = build_checked(commands, Mode::Strict)?;
+    let vfs = Vfs::new(tree);
+
+    let mut mismatches = generated
+        .sizes
+        .into_iter()
+        .filter_map(|(path, disk)| {
+            let tree = vfs.size(&path).ok();
+
+            (tree != Some(disk)).then_some(SizeMismatch { path, disk, tree })
+        })
+        .collect::<Vec<SizeMismatch>>();
+    mismatches.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(mismatches)
+}
+
+/// The size of every directory in the tree, including the root.
+fn directory_sizes(tree: &FileTree) -> Vec<usize> {
+    let mut sizes = Vec::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(tree);
+
+    while let Some(node) = queue.pop_front() {
+        queue.extend(node.directories.values().map(|dir| dir.as_ref()));
+        sizes.push(node.size());
+    }
+
+    sizes
+}
+
+fn part1(input: &str) -> Result<usize> {
+    let commands = parse_commands(input)?;
+    let tree = build_file_tree(commands)?;
+
+    Ok(directory_sizes(&tree)
+        .into_iter()
+        .filter(|&size| size <= 100_000)
+        .sum())
+}
+
+const DISK_SIZE: usize = 70_000_000;
+const UPDATE_SIZE: usize = 30_000_000;
+const REQ_SIZE: usize = DISK_SIZE - UPDATE_SIZE;
+
+fn part2(input: &str) -> Result<usize> {
+    let commands = parse_commands(input)?;
+    let tree = build_file_tree(commands)?;
+
+    let min_size_remove = Disk::PUZZLE.needed(tree.size())?;
+
+    Ok(smallest_directory(&tree, min_size_remove).unwrap_or(DISK_SIZE))
+}
+
+/// The size of the disk and how much free space the update needs, so that part 2 can be asked
+/// about disks other than the puzzle's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Disk {
+    size: usize,
+    update: usize,
+}
+
+impl Disk {
+    const PUZZLE: Disk = Disk {
+        size: DISK_SIZE,
+        update: UPDATE_SIZE,
+    };
+
+    /// How much has to be deleted from a disk with `used` taken up before the update fits.
+    fn needed(&self, used: usize) -> Result<usize> {
+        if self.update > self.size {
+            bail!(
+                "An update of {} can't fit on a disk of {}",
+                self.update,
+                self.size
+            );
+        }
+
+        Ok(used.saturating_sub(self.size - self.update))
+    }
+}
+
+/// The size of the smallest directory that frees at least `needed` on its own.
+fn smallest_directory(tree: &FileTree, needed: usize) -> Option<usize> {
+    directory_sizes(tree)
+        .into_iter()
+        .filter(|&size| size >= needed)
+        .min()
+}
+
+/// A set of totals from `0` to `limit`, stored as a bitset.
+#[derive(Debug, Clone)]
+struct SumSet {
+    words: Vec<u64>,
+    limit: usize,
+}
+
+impl SumSet {
+    /// A set holding just `0`, for when nothing has been deleted yet.
+    fn new(limit: usize) -> Self {
+        let mut words = vec![0; limit / 64 + 1];
+        words[0] = 1;
+
+        SumSet { words, limit }
+    }
+
+    fn contains(&self, sum: usize) -> bool {
+        sum <= self.limit && self.words[sum / 64] & (1 << (sum % 64)) != 0
+    }
+
+    /// Adds every total in `other` plus `shift`, dropping any that go past the limit.
+    fn union_shifted(&mut self, other: &SumSet, shift: usize) {
+        let (word_shift, bit_shift) = (shift / 64, shift % 64);
+        let len = self.words.len();
+
+        for (i, &word) in other.words.iter().enumerate() {
+            let dest = i + word_shift;
+            if dest >= len {
+                break;
+            }
+
+            self.words[dest] |= word << bit_shift;
+            if bit_shift > 0 && dest + 1 < len {
+                self.words[dest + 1] |= word >> (64 - bit_shift);
+            }
+        }
+
+        let spare = 63 - self.limit % 64;
+        self.words[len - 1] &= u64::MAX >> spare;
+    }
+
+    /// The smallest total that is at least `min`.
+    fn first_from(&self, min: usize) -> Option<usize> {
+        (min..=self.limit).find(|&sum| self.contains(sum))
+    }
+}
+
+/// Finds the cheapest set of deletions that frees enough space. Deleting a directory deletes
+/// everything under it, so the chosen paths are never nested.
+///
+/// Nodes are decided one at a time in the order [`Walk`] visits them, tracking every total that
+/// could have been freed so far. Deleting a directory skips over its contents, while keeping it
+/// moves on to its children.
+struct Planner {
+    include_files: bool,
+}
+
+impl Planner {
+    /// Deleting something empty never helps, so only nodes that free some space are considered.
+    fn is_candidate(&self, node: &Node) -> bool {
+        node.size() > 0 && (node.is_dir() || self.include_files)
+    }
+
+    /// The children worth visiting, which leaves out empty nodes, and files unless they can be
+    /// deleted.
+    fn children<'a>(&self, tree: &'a FileTree) -> Vec<(String, Node<'a>)> {
+        children(tree)
+            .into_iter()
+            .filter(|(_, node)| self.is_candidate(node))
+            .collect()
+    }
+
+    /// The totals that can have been freed once `node` and everything under it has been decided,
+    /// given the ones that could have been freed before reaching it.
+    fn extend(&self, node: Node, before: &SumSet) -> SumSet {
+        let mut after = before.clone();
+
+        if let Node::Dir(tree) = node {
+            for (_, child) in self.children(tree) {
+                after = self.extend(child, &after);
+            }
+        }
+
+        after.union_shifted(before, node.size());
+
+        after
+    }
+
+    /// Works out which deletions under `node` make up `target`, recording them in `picked`, and
+    /// returns what is left of the target for the nodes before it. When the target can be made
+    /// up either way, the deletions below `node` are preferred over `node` itself.
+    fn pick(
+        &self,
+        path: &mut Vec<String>,
+        node: Node,
+        before: &SumSet,
+        target: usize,
+        picked: &mut Vec<ReportRow>,
+    ) -> usize {
+        let children = match node {
+            Node::Dir(tree) => self.children(tree),
+            Node::File(_) => Vec::new(),
+        };
+
+        let mut sets = vec![before.clone()];
+        for (_, child) in &children {
+            sets.push(self.extend(*child, &sets[sets.len() - 1]));
+        }
+
+        if sets[sets.len() - 1].contains(target) {
+            let mut target = target;
+            for (i, (name, child)) in children.into_iter().enumerate().rev() {
+                path.push(name);
+                target = self.pick(path, child, &sets[i], target, picked);
+                path.pop();
+            }
+
+            return target;
+        }
+
+        let size = node.size();
+        if target >= size && before.contains(target - size) {
+            picked.push(ReportRow {
+                path: format_path(path),
+                size,
+            });
+
+            return target - size;
+        }
+
+        target
+    }
+}
+
+/// A set of deletions that frees enough space for the update, along with what part 2's single
+/// directory would have freed instead.
+#[derive(Debug, PartialEq, Eq)]
+struct DeletionPlan {
+    needed: usize,
+    deletions: Vec<ReportRow>,
+    freed: usize,
+    single: Option<usize>,
+}
+
+impl Display for DeletionPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for row in &self.deletions {
+            writeln!(f, "{}\t{}", row.size, row.path)?;
+        }
+
+        writeln!(f, "frees {} of {} needed", self.freed, self.needed)?;
+
+        match self.single {
+            Some(single) => writeln!(
+                f,
+                "the smallest single directory frees {} ({} more)",
+                single,
+                single - self.freed
+            ),
+            None => writeln!(f, "no single directory frees enough"),
+        }
+    }
+}
+
+/// Plans the deletions with the smallest total size that free enough space for the update,
+/// optionally deleting individual files as well as directories.
+fn plan_deletion(tree: &FileTree, disk: Disk, include_files: bool) -> Result<DeletionPlan> {
+    let needed = disk.needed(tree.size())?;
+    let single = smallest_directory(tree, needed);
+
+    // Nothing bigger than the best single deletion can be part of the best plan, and the root
+    // directory always frees enough on its own
+    let root = Node::Dir(tree);
+    let limit = Walk {
+        stack: vec![(Vec::new(), root)],
+    }
+    .map(|(_, node)| node)
+    .filter(|node| (node.is_dir() || include_files) && node.size() >= needed)
+    .map(|node| node.size())
+    .min()
+    .unwrap_or(needed);
+
+    let planner = Planner { include_files };
+    let sums = planner.extend(root, &SumSet::new(limit));
+    let freed = sums
+        .first_from(needed)
+        .context("Unable to free enough space")?;
+
+    // Working back to the deletions recomputes parts of the tree, which is cheaper now that
+    // nothing above the best total matters
+    let mut deletions = Vec::new();
+    let start = SumSet::new(freed);
+    let left = planner.pick(&mut Vec::new(), root, &start, freed, &mut deletions);
+    if left != 0 {
+        bail!("Deletion plan doesn't add up, {} left over", left);
+    }
+    deletions.sort_by(|a, b| a.path.cmp(&b.path));
+
+    Ok(DeletionPlan {
+        needed,
+        deletions,
+        freed,
+        single,
+    })
+}
+
+#[cfg(test)]
+mod tests_example {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use crate::util;
+
+    pub(super) const INPUT: &str = indoc! {"
+        $ cd /
+        $ ls
+        dir a
+        14848514 b.txt
+        8504156 c.dat
+        dir d
+        $ cd a
+        $ ls
+        dir e
+        29116 f
+        2557 g
+        62596 h.lst
+        $ cd e
+        $ ls
+        584 i
+        $ cd ..
+        $ cd ..
+        $ cd d
+        $ ls
+        4060174 j
+        8033020 d.log
+        5626152 d.ext
+        7214296 k
+    "};
+
+    #[test]
+    fn test_part1() -> Result<()> {
+        let result = super::part1(util::format_input(INPUT))?;
+
+        assert_eq!(result, 95437);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_part2() -> Result<()> {
+        let result = super::part2(util::format_input(INPUT))?;
+
+        assert_eq!(result, 24933642);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_vfs {
+    use anyhow::Result;
+
+    use super::{Stat, Vfs};
+    use crate::util;
+
+    fn example() -> Result<Vfs> {
+        Vfs::parse(util::format_input(super::tests_example::INPUT))
+    }
+
+    #[test]
+    fn test_paths() -> Result<()> {
+        let mut vfs = example()?;
+
+        assert_eq!(vfs.size("/a/e")?, 584);
+        assert_eq!(vfs.size("/")?, 48381165);
+
+        vfs.cd("/a/e")?;
+        assert_eq!(vfs.cwd(), "/a/e");
+        assert_eq!(vfs.size("../../d")?, 24933642);
+        assert_eq!(vfs.size("./i")?, 584);
+
+        vfs.cd("..")?;
+        assert_eq!(vfs.cwd(), "/a");
+        assert_eq!(
+            vfs.stat("e/../h.lst")?,
+            Stat {
+                path: "/a/h.lst".to_string(),
+                is_dir: false,
+                size: 62596
+            }
+        );
+
+        assert!(vfs.cd("h.lst").is_err());
+        assert!(vfs.cd("../..").is_err());
+        assert!(vfs.get("/a/missing").is_err());
+        assert!(vfs.get("/b.txt/x").is_err());
+        assert_eq!(vfs.cwd(), "/a");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_and_walk() -> Result<()> {
+        let vfs = example()?;
+
+        let listing = vfs
+            .list("/a")?
+            .into_iter()
+            .map(|(name, node)| format!("{} {}", name, node.size()))
+            .collect::<Vec<String>>();
+        assert_eq!(listing, vec!["e 584", "f 29116", "g 2557", "h.lst 62596"]);
+
+        let paths = vfs
+            .walk()
+            .filter(|(_, node)| node.is_dir())
+            .map(|(path, _)| path)
+            .collect::<Vec<String>>();
+        assert_eq!(paths, vec!["/", "/a", "/a/e", "/d"]);
+        assert_eq!(vfs.walk().count(), 14);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_sizes {
+    use std::time::Instant;
+
+    use anyhow::Result;
+
+    use super::FileTree;
+    use crate::util::rng::Rng;
+
+    /// Generates a transcript with `dirs` directories spread over a fairly shallow tree, with a
+    /// few files in each.
+    pub(super) fn generate_transcript(rng: &mut Rng, dirs: usize) -> String {
+        let mut output = vec!["$ cd /".to_string()];
+        let mut remaining = dirs - 1;
+        let mut depth = 0;
+
+        // Each entry is the list of subdirectories still to visit at that level
+        let mut pending = vec![Vec::new()];
+
+        loop {
+            output.push("$ ls".to_string());
+
+            let count = if depth < 12 {
+                (rng.range(1, 12) as usize).min(remaining)
+            } else {
+                0
+            };
+            remaining -= count;
+
+            let subdirs = (0..count).map(|i| format!("d{}", i)).collect::<Vec<_>>();
+            for dir in &subdirs {
+                output.push(format!("dir {}", dir));
+            }
+
+            for i in 0..rng.range(0, 4) {
+                output.push(format!("{} f{}.txt", rng.range(1, 300_000), i));
+            }
+
+            *pending.last_mut().unwrap() = subdirs.into_iter().rev().collect();
+
+            // Move on to the next unvisited directory, backing out of finished ones
+            loop {
+                match pending.last_mut().unwrap().pop() {
+                    Some(dir) => {
+                        output.push(format!("$ cd {}", dir));
+                        pending.push(Vec::new());
+                        depth += 1;
+                        break;
+                    }
+                    None if depth == 0 => return output.join("\n") + "\n",
+                    None => {
+                        output.push("$ cd ..".to_string());
+                        pending.pop();
+                        depth -= 1;
+                    }
+                }
+            }
+        }
+    }
+
+    fn recompute_size(tree: &FileTree) -> usize {
+        tree.files.iter().map(|file| file.size).sum::<usize>()
+            + tree
+                .directories
+                .values()
+                .map(|dir| recompute_size(dir))
+                .sum::<usize>()
+    }
+
+    fn check_sizes(tree: &FileTree) {
+        assert_eq!(tree.size(), recompute_size(tree));
+
+        for dir in tree.directories.values() {
+            check_sizes(dir);
+        }
+    }
+
+    #[test]
+    fn test_cached_sizes() -> Result<()> {
+        let transcript = generate_transcript(&mut Rng::new(7), 2_000);
+        let tree = super::build_file_tree(super::parse_commands(&transcript)?)?;
+
+        assert_eq!(super::directory_sizes(&tree).len(), 2_000);
+        check_sizes(&tree);
+
+        // Listing a directory again keeps the contents of its subdirectories
+        let tree = super::build_file_tree(super::parse_commands(
+            "$ cd /\n$ ls\ndir a\n$ cd a\n$ ls\n10 b\n$ cd ..\n$ ls\ndir a",
+        )?)?;
+        assert_eq!(tree.size(), 10);
+        check_sizes(&tree);
+
+        let mut tree = tree;
+        assert_eq!(tree.remove(&["a".to_string(), "b".to_string()]), Some(10));
+        assert_eq!(tree.remove(&["a".to_string(), "b".to_string()]), None);
+        check_sizes(&tree);
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_large_transcript() -> Result<()> {
+        let transcript = generate_transcript(&mut Rng::new(41), 100_000);
+        assert_eq!(transcript.matches("\ndir ").count() + 1, 100_000);
+
+        let start = Instant::now();
+        let commands = super::parse_commands(&transcript)?;
+        println!("parse in {:?}", start.elapsed());
+
+        let start = Instant::now();
+        super::build_file_tree(commands)?;
+        println!("build in {:?}", start.elapsed());
+
+        let start = Instant::now();
+        let part1 = super::part1(&transcript)?;
+        println!("part1 = {} in {:?}", part1, start.elapsed());
+
+        let start = Instant::now();
+        let part2 = super::part2(&transcript)?;
+        println!("part2 = {} in {:?}", part2, start.elapsed());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_report {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{Report, ReportRow};
+    use crate::util;
+
+    fn input() -> &'static str {
+        util::format_input(super::tests_example::INPUT)
+    }
+
+    #[test]
+    fn test_tree() -> Result<()> {
+        assert_eq!(
+            super::report(input(), &Report::Tree)?,
+            indoc! {"
+                - / (dir)
+                  - a (dir)
+                    - e (dir)
+                      - i (file, size=584)
+                    - f (file, size=29116)
+                    - g (file, size=2557)
+                    - h.lst (file, size=62596)
+                  - b.txt (file, size=14848514)
+                  - c.dat (file, size=8504156)
+                  - d (dir)
+                    - d.ext (file, size=5626152)
+                    - d.log (file, size=8033020)
+                    - j (file, size=4060174)
+                    - k (file, size=7214296)
+            "}
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_du() -> Result<()> {
+        let vfs = super::Vfs::parse(input())?;
+
+        assert_eq!(
+            super::du_rows(&vfs)[3],
+            ReportRow {
+                path: "/a/e".to_string(),
+                size: 584
+            }
+        );
+        assert_eq!(
+            super::report(input(), &Report::Du)?,
+            "47M\t/\n24M\t/d\n93K\t/a\n584\t/a/e\n"
+        );
+
+        // Values that only reach the next unit or format once they're rounded up
+        assert_eq!(super::human_size(1023), "1023");
+        assert_eq!(super::human_size(1024), "1.0K");
+        assert_eq!(super::human_size(10239), "10K");
+        assert_eq!(super::human_size(10240), "10K");
+        assert_eq!(super::human_size(10241), "11K");
+        assert_eq!(super::human_size(1048575), "1.0M");
+        assert_eq!(super::human_size(1048577), "1.1M");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_top() -> Result<()> {
+        assert_eq!(
+            super::report(input(), &"top=3".parse()?)?,
+            "15M\t/b.txt\n8.2M\t/c.dat\n7.7M\t/d/d.log\n"
+        );
+
+        let input = "$ cd /\n$ ls\n1048575 a\n10239 b\n1073741823 c";
+        assert_eq!(
+            super::report(input, &"top".parse()?)?,
+            "1.0G\t/c\n1.0M\t/a\n10K\t/b\n"
+        );
+
+        assert_eq!("top".parse::<Report>()?, Report::Top(10));
+        assert!("top=x".parse::<Report>().is_err());
+        assert!("size".parse::<Report>().is_err());
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_consistency {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{Diagnostic, Mode, Problem};
+    use crate::util;
+
+    const INPUT: &str = indoc! {"
+        $ cd /
+        $ ls
+        dir a
+        100 b
+        100 b
+        $ cd a
+        $ ls
+        10 c
+        $ cd /
+        $ ls
+        dir a
+        150 b
+        $ cd x
+        $ cd /
+        $ cd ..
+        $ ls
+        dir a
+        150 b
+        dir x
+    "};
+
+    #[test]
+    fn test_lenient() -> Result<()> {
+        let commands = super::parse_commands(util::format_input(INPUT))?;
+        let (tree, diagnostics) = super::build_checked(commands, Mode::Lenient)?;
+
+        assert_eq!(
+            diagnostics,
+            vec![
+                Diagnostic {
+                    command: 2,
+                    problem: Problem::DuplicateEntry("/b".to_string())
+                },
+                Diagnostic {
+                    command: 6,
+                    problem: Problem::ConflictingEntry {
+                        path: "/b".to_string(),
+                        previous: "a file of size 100".to_string(),
+                        listed: "a file of size 150".to_string()
+                    }
+                },
+                Diagnostic {
+                    command: 7,
+                    problem: Problem::UnlistedDirectory("/x".to_string())
+                },
+                Diagnostic {
+                    command: 9,
+                    problem: Problem::AboveRoot
+                },
+            ]
+        );
+
+        // The file is only counted once, with its latest size, and the relisting of the root
+        // keeps the contents of /a
+        assert_eq!(tree.size(), 160);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_strict() -> Result<()> {
+        let commands = super::parse_commands(util::format_input(INPUT))?;
+        let err = super::build_checked(commands, Mode::Strict).unwrap_err();
+
+        assert_eq!(
+            err.to_string(),
+            "Inconsistent transcript: command 2: /b is listed more than once"
+        );
+
+        let commands = super::parse_commands(util::format_input(super::tests_example::INPUT))?;
+        let (_, diagnostics) = super::build_checked(commands, Mode::Strict)?;
+        assert!(diagnostics.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_missing_entries() -> Result<()> {
+        let commands = super::parse_commands("$ ls\n10 a\n20 b\n$ ls\n10 a\n")?;
+        let (tree, diagnostics) = super::build_checked(commands, Mode::Lenient)?;
+
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "command 2: /b was previously listed as a file of size 20 but is now missing"
+        );
+        assert_eq!(tree.size(), 30);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_disk {
+    use std::fs;
+
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{TranscriptOptions, Traversal};
+    use crate::util::temp_dir::TempDir;
+
+    /// Writes a file of `size` bytes at `path` under `dir`, creating any missing directories.
+    fn write_file(dir: &TempDir, path: &str, size: usize) -> Result<()> {
+        let path = dir.path().join(path);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(path, vec![b'x'; size])?;
+
+        Ok(())
+    }
+
+    fn sample() -> Result<TempDir> {
+        let dir = TempDir::new("day07")?;
+        write_file(&dir, "b.txt", 120)?;
+        write_file(&dir, "a/e/i", 584)?;
+        write_file(&dir, "a/f", 291)?;
+        write_file(&dir, "a/g", 205)?;
+        write_file(&dir, "d/j", 400)?;
+        write_file(&dir, "d/k", 0)?;
+        fs::create_dir(dir.path().join("empty"))?;
+
+        Ok(dir)
+    }
+
+    const ALL_OPTIONS: [TranscriptOptions; 4] = [
+        TranscriptOptions {
+            traversal: Traversal::DepthFirst,
+            reverse: false,
+            redundant: false,
+        },
+        TranscriptOptions {
+            traversal: Traversal::DepthFirst,
+            reverse: true,
+            redundant: true,
+        },
+        TranscriptOptions {
+            traversal: Traversal::BreadthFirst,
+            reverse: false,
+            redundant: true,
+        },
+        TranscriptOptions {
+            traversal: Traversal::BreadthFirst,
+            reverse: true,
+            redundant: false,
+        },
+    ];
+
+    #[test]
+    fn test_depth_first() -> Result<()> {
+        let dir = sample()?;
+        let generated = super::disk_transcript(dir.path(), ALL_OPTIONS[0])?;
+
+        let expected = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            120 b.txt
+            dir d
+            dir empty
+            $ cd a
+            $ ls
+            dir e
+            291 f
+            205 g
+            $ cd e
+            $ ls
+            584 i
+            $ cd ..
+            $ cd ..
+            $ cd d
+            $ ls
+            400 j
+            0 k
+            $ cd ..
+            $ cd empty
+            $ ls
+            $ cd ..
+        "};
+
+        assert_eq!(generated.transcript, expected);
+        assert_eq!(generated.sizes["/"], 1600);
+        assert_eq!(generated.sizes["/a"], 1080);
+        assert_eq!(generated.sizes["/empty"], 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_breadth_first_redundant() -> Result<()> {
+        let dir = TempDir::new("day07")?;
+        write_file(&dir, "a/b/c", 10)?;
+
+        let options = TranscriptOptions {
+            traversal: Traversal::BreadthFirst,
+            reverse: false,
+            redundant: true,
+        };
+        let generated = super::disk_transcript(dir.path(), options)?;
+
+        let expected = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            $ cd /
+            $ ls
+            dir a
+            $ cd /
+            $ cd a
+            $ ls
+            dir b
+            $ cd /
+            $ cd a
+            $ ls
+            dir b
+            $ cd /
+            $ cd a
+            $ cd b
+            $ ls
+            10 c
+            $ cd /
+            $ cd a
+            $ cd b
+            $ ls
+            10 c
+        "};
+
+        assert_eq!(generated.transcript, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_sizes_match_disk() -> Result<()> {
+        let dir = sample()?;
+
+        for options in ALL_OPTIONS {
+            assert_eq!(super::check_against_disk(dir.path(), options)?, vec![]);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_skips_unrepresentable_names() -> Result<()> {
+        let dir = sample()?;
+        write_file(&dir, "has space/l", 1000)?;
+
+        let generated = super::disk_transcript(dir.path(), ALL_OPTIONS[0])?;
+
+        assert_eq!(generated.skipped, vec![dir.path().join("has space")]);
+        assert_eq!(generated.sizes["/"], 1600);
+        assert!(!generated.transcript.contains("space"));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_shell {
+    use std::io::Cursor;
+
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::tests_example::INPUT;
+    use crate::util;
+
+    fn run(commands: &str) -> Result<String> {
+        let mut out = Vec::new();
+        super::shell(util::format_input(INPUT), Cursor::new(commands), &mut out)?;
+
+        Ok(String::from_utf8(out)?)
+    }
+
+    #[test]
+    fn test_navigation() -> Result<()> {
+        let output = run(indoc! {"
+            pwd
+            cd a
+            ls
+            cd e
+            pwd
+            cd /d
+            du
+            cd nope
+            exit
+            pwd
+        "})?;
+
+        let expected = indoc! {"
+            /$ /
+            /$ /a$ dir e
+            29116 f
+            2557 g
+            62596 h.lst
+            /a$ /a/e$ /a/e
+            /a/e$ /d$ 24933642\t/d
+            /d$ error: No such file or directory: /d/nope
+            /d$ "};
+
+        assert_eq!(output, expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_find_and_rm() -> Result<()> {
+        let output = run(indoc! {"
+            df
+            find -size +8000000
+            find /a -size +20000
+            rm d
+            rm d
+            df
+            du
+        "})?;
+
+        let expected = indoc! {"
+            /$ used 48381165
+            free 21618835
+            needed 8381165
+            /$ 48381165\t/
+            14848514\t/b.txt
+            8504156\t/c.dat
+            24933642\t/d/
+            8033020\t/d/d.log
+            /$ 94853\t/a/
+            29116\t/a/f
+            62596\t/a/h.lst
+            /$ freed 24933642
+            /$ error: No such file or directory: /d
+            /$ used 23447523
+            free 46552477
+            needed 0
+            /$ 23447523\t/
+            94853\t/a
+            584\t/a/e
+        "};
+
+        assert_eq!(output, expected.to_string() + "/$ \n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_working_directory() -> Result<()> {
+        let output = run("cd /a/e\nrm /a\nrm .\nrm i\ncd ..\nrm e\nls\n")?;
+
+        assert!(output.contains("error: Can't remove the working directory or its parents: /a\n"));
+        assert!(output.contains("freed 584\n"));
+        assert!(output.ends_with("/a$ 29116 f\n2557 g\n62596 h.lst\n/a$ \n"));
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_commands {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{Command, Diagnostic, Mode, Problem, Vfs};
+    use crate::util;
+
+    fn build(input: &str, mode: Mode) -> Result<(Vfs, Vec<Diagnostic>)> {
+        let commands = super::parse_commands(input)?;
+        let (tree, diagnostics) = super::build_checked(commands, mode)?;
+
+        Ok((Vfs::new(tree), diagnostics))
+    }
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let commands = super::parse_commands(indoc! {"
+            $ pwd
+            /a
+            $ pwd
+            $ mkdir b
+            $ touch 100 b/c.txt
+            $ rm /b
+            $ mv a ../d
+        "})?;
+
+        assert!(matches!(&commands[0], Command::Pwd(Some(dir)) if dir == "/a"));
+        assert!(matches!(&commands[1], Command::Pwd(None)));
+        assert!(matches!(&commands[2], Command::Mkdir(path) if path == "b"));
+        assert!(matches!(&commands[3], Command::Touch(100, path) if path == "b/c.txt"));
+        assert!(matches!(&commands[4], Command::Rm(path) if path == "/b"));
+        assert!(matches!(&commands[5], Command::Mv(from, to) if from == "a" && to == "../d"));
+
+        assert!(super::parse_commands("$ touch big a\n").is_err());
+        assert!(super::parse_commands("$ mv a\n").is_err());
+        assert!(super::parse_commands("$ mkdir a\nb\n").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_simulate() -> Result<()> {
+        let input = indoc! {"
+            $ cd /
+            $ mkdir a
+            $ mkdir a/b
+            $ cd a/b
+            $ touch 100 c
+            $ touch 50 ../d
+            $ pwd
+            /a/b
+            $ cd /
+            $ touch 20 e
+            $ touch 30 e
+            $ mv a f
+            $ mv e f/b
+            $ mv f/d f/g
+            $ cd f/b
+            $ pwd
+            /f/b
+            $ cd /
+            $ rm f/b/c
+        "};
+
+        let (vfs, diagnostics) = build(util::format_input(input), Mode::Strict)?;
+
+        assert_eq!(diagnostics, vec![]);
+        assert_eq!(vfs.size("/")?, 80);
+        assert_eq!(vfs.size("/f")?, 80);
+        assert_eq!(vfs.size("/f/b")?, 30);
+        assert_eq!(vfs.size("/f/b/e")?, 30);
+        assert_eq!(vfs.size("/f/g")?, 50);
+        assert!(vfs.get("/a").is_err());
+        assert!(vfs.get("/f/d").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_problems() -> Result<()> {
+        let input = indoc! {"
+            $ cd /
+            $ mkdir a
+            $ mkdir a
+            $ touch 10 x/y
+            $ rm nope
+            $ cd a
+            $ rm /a
+            $ pwd
+            /b
+            $ mv /a /a/c
+            $ cd /
+            $ touch 5 a
+            $ mkdir ../z
+        "};
+
+        let (vfs, diagnostics) = build(util::format_input(input), Mode::Lenient)?;
+        let problems = diagnostics
+            .into_iter()
+            .map(|diagnostic| (diagnostic.command, diagnostic.problem))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            problems,
+            vec![
+                (3, Problem::AlreadyExists("/a".to_string())),
+                (4, Problem::NotFound("/x".to_string())),
+                (5, Problem::NotFound("/nope".to_string())),
+                (7, Problem::RemovesWorkingDirectory("/a".to_string())),
+                (
+                    8,
+                    Problem::WrongWorkingDirectory {
+                        printed: "/b".to_string(),
+                        actual: "/a".to_string(),
+                    }
+                ),
+                (
+                    9,
+                    Problem::MoveIntoItself {
+                        from: "/a".to_string(),
+                        to: "/a/c".to_string(),
+                    }
+                ),
+                (11, Problem::AlreadyExists("/a".to_string())),
+                (12, Problem::AboveRoot),
+            ]
+        );
+
+        // The file under the missing directory is still created
+        assert_eq!(vfs.size("/x/y")?, 10);
+        assert!(vfs.get("/a").is_ok_and(|node| node.is_dir()));
+
+        let strict = build(util::format_input(input), Mode::Strict);
+        assert_eq!(
+            strict.unwrap_err().to_string(),
+            "Inconsistent transcript: command 3: /a already exists"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_planner {
+    use std::time::Instant;
+
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{tests_example::INPUT, tests_sizes::generate_transcript, Disk, ReportRow, Vfs};
+    use crate::util::{self, rng::Rng};
+
+    fn row(path: &str, size: usize) -> ReportRow {
+        ReportRow {
+            path: path.to_string(),
+            size,
+        }
+    }
+
+    #[test]
+    fn test_example() -> Result<()> {
+        let vfs = Vfs::parse(util::format_input(INPUT))?;
+
+        let plan = super::plan_deletion(&vfs.root, Disk::PUZZLE, false)?;
+        assert_eq!(plan.needed, 8381165);
+        assert_eq!(plan.deletions, vec![row("/d", 24933642)]);
+        assert_eq!(plan.single, Some(24933642));
+
+        let plan = super::plan_deletion(&vfs.root, Disk::PUZZLE, true)?;
+        assert_eq!(plan.deletions, vec![row("/c.dat", 8504156)]);
+        assert_eq!(
+            plan.to_string(),
+            indoc! {"
+                8504156\t/c.dat
+                frees 8504156 of 8381165 needed
+                the smallest single directory frees 24933642 (16429486 more)
+            "}
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_several_directories() -> Result<()> {
+        let input = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            dir b
+            dir c
+            $ cd a
+            $ ls
+            60 x
+            $ cd ../b
+            $ ls
+            50 y
+            $ cd ../c
+            $ ls
+            200 z
+        "};
+        let vfs = Vfs::parse(input)?;
+        let disk = Disk {
+            size: 400,
+            update: 190,
+        };
+
+        let plan = super::plan_deletion(&vfs.root, disk, false)?;
+        assert_eq!(plan.needed, 100);
+        assert_eq!(plan.deletions, vec![row("/a", 60), row("/b", 50)]);
+        assert_eq!(plan.freed, 110);
+        assert_eq!(plan.single, Some(200));
+
+        // Plenty of room already
+        let disk = Disk {
+            size: 1000,
+            update: 190,
+        };
+        let plan = super::plan_deletion(&vfs.root, disk, false)?;
+        assert_eq!(plan.deletions, vec![]);
+        assert_eq!(plan.freed, 0);
+
+        let disk = Disk {
+            size: 100,
+            update: 190,
+        };
+        assert!(super::plan_deletion(&vfs.root, disk, false).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_empty_and_nested() -> Result<()> {
+        let input = indoc! {"
+            $ cd /
+            $ ls
+            dir a
+            dir e
+            $ cd a
+            $ ls
+            60 x
+        "};
+        let vfs = Vfs::parse(input)?;
+
+        // An empty directory frees nothing, so it's never worth deleting
+        let disk = Disk {
+            size: 1000,
+            update: 100,
+        };
+        let plan = super::plan_deletion(&vfs.root, disk, false)?;
+        assert_eq!(plan.needed, 0);
+        assert_eq!(plan.deletions, vec![]);
+
+        // `/` and `/a` free the same amount, and the deeper one is less to delete
+        let disk = Disk {
+            size: 100,
+            update: 90,
+        };
+        let plan = super::plan_deletion(&vfs.root, disk, false)?;
+        assert_eq!(plan.needed, 50);
+        assert_eq!(plan.deletions, vec![row("/a", 60)]);
+
+        let plan = super::plan_deletion(&vfs.root, disk, true)?;
+        assert_eq!(plan.deletions, vec![row("/a/x", 60)]);
+
+        Ok(())
+    }
+
+    /// Tries every set of paths that aren't nested in each other.
+    fn brute_force(vfs: &Vfs, needed: usize, include_files: bool) -> usize {
+        let candidates = vfs
+            .walk()
+            .filter(|(_, node)| node.is_dir() || include_files)
+            .map(|(path, node)| (path, node.size()))
+            .collect::<Vec<_>>();
+
+        let mut best = usize::MAX;
+        for mask in 0..(1usize << candidates.len()) {
+            let chosen = (0..candidates.len())
+                .filter(|i| mask & (1 << i) != 0)
+                .map(|i| &candidates[i])
+                .collect::<Vec<_>>();
+
+            let nested = chosen
+                .iter()
+                .any(|(a, _)| chosen.iter().any(|(b, _)| a != b && super::is_below(a, b)));
+            let total = chosen.iter().map(|(_, size)| size).sum::<usize>();
+
+            if !nested && total >= needed {
+                best = best.min(total);
+            }
+        }
+
+        best
+    }
+
+    #[test]
+    fn test_matches_brute_force() -> Result<()> {
+        let mut rng = Rng::new(47);
+
+        for round in 0..40 {
+            let include_files = round % 2 == 1;
+            let dirs = if include_files { 3 } else { 10 };
+
+            let vfs = Vfs::parse(&generate_transcript(&mut rng, dirs))?;
+            let used = vfs.root.size();
+            let disk = Disk {
+                size: used * 2,
+                update: used + rng.range(0, used as u64) as usize,
+            };
+
+            let plan = super::plan_deletion(&vfs.root, disk, include_files)?;
+            let needed = disk.needed(used)?;
+
+            assert_eq!(plan.freed, brute_force(&vfs, needed, include_files));
+            assert_eq!(
+                plan.deletions.iter().map(|row| row.size).sum::<usize>(),
+                plan.freed
+            );
+            if let Some(single) = plan.single {
+                assert!(single >= plan.freed);
+            }
+
+            for a in &plan.deletions {
+                for b in &plan.deletions {
+                    assert!(a == b || !super::is_below(&a.path, &b.path));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_planner() -> Result<()> {
+        let vfs = Vfs::parse(&generate_transcript(&mut Rng::new(7), 200))?;
+        let used = vfs.root.size();
+        let disk = Disk {
+            size: used + used / 2,
+            update: used / 2 + used / 10,
+        };
+
+        for include_files in [false, true] {
+            let start = Instant::now();
+            let plan = super::plan_deletion(&vfs.root, disk, include_files)?;
+            println!(
+                "files = {}: freed {} of {} with {} deletions (single {:?}) in {:?}",
+                include_files,
+                plan.freed,
+                plan.needed,
+                plan.deletions.len(),
+                plan.single,
+                start.elapsed()
+            );
+        }
 
         Ok(())
     }
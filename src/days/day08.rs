@@ -1,6 +1,9 @@
-use std::fmt::Debug;
+use std::{fmt::Debug, io::Write};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+
+/// Trees are a single digit tall, so there are only this many heights.
+const HEIGHTS: usize = 10;
 
 #[derive(Debug, Clone, Copy)]
 struct GridItem {
@@ -18,7 +21,7 @@ impl Grid {
 
         let lines = input.split("\n").map(|line| line.chars());
 
-        for line in lines {
+        for (y, line) in lines.enumerate() {
             let mut row = Vec::new();
 
             for char in line {
@@ -27,6 +30,15 @@ impl Grid {
                 row.push(GridItem { height });
             }
 
+            // Everything else indexes rows by the first row's width, so the grid has to be a
+            // full rectangle
+            if row.is_empty() {
+                bail!("Row {} is empty", y + 1);
+            }
+            if let Some(first) = trees.first().map(Vec::len).filter(|&len| len != row.len()) {
+                bail!("Row {} has {} trees, expected {}", y + 1, row.len(), first);
+            }
+
             trees.push(row);
         }
 
@@ -130,72 +142,489 @@ fn check_y_distance<R: IntoIterator<Item = i32> + Debug>(
     return score;
 }
 
-fn part1(input: &str) -> Result<usize> {
-    let grid = Grid::new(input)?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+    UpLeft,
+    UpRight,
+    DownLeft,
+    DownRight,
+}
 
-    let width = grid.width();
-    let height = grid.height();
+impl Direction {
+    const CARDINAL: [Direction; 4] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+    ];
 
-    let mut total = 0;
+    const ALL: [Direction; 8] = [
+        Direction::Left,
+        Direction::Right,
+        Direction::Up,
+        Direction::Down,
+        Direction::UpLeft,
+        Direction::UpRight,
+        Direction::DownLeft,
+        Direction::DownRight,
+    ];
 
-    for x in 0..width {
-        for y in 0..height {
-            let item = grid.get(x, y).context("Failed to get grid item.")?;
-            let tree_height = item.height;
+    /// How far one tree along moves in `x` and `y`, looking this way.
+    fn step(&self) -> (i32, i32) {
+        match self {
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::UpLeft => (-1, -1),
+            Direction::UpRight => (1, -1),
+            Direction::DownLeft => (-1, 1),
+            Direction::DownRight => (1, 1),
+        }
+    }
+}
 
-            let mut visible = false;
+/// Which trees stop a tree from seeing any further.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Blocking {
+    /// Trees at least as tall block the view, as in the puzzle
+    AtLeastAsTall,
 
-            visible = visible || check_x_range(&grid, 0..x, y, tree_height);
-            visible = visible || check_x_range(&grid, (x + 1)..width, y, tree_height);
+    /// Only strictly taller trees block the view
+    Taller,
+}
 
-            visible = visible || check_y_range(&grid, 0..y, x, tree_height);
-            visible = visible || check_y_range(&grid, (y + 1)..height, x, tree_height);
+/// How trees look out over the forest. The default is the puzzle's: four directions, no limit on
+/// how far a tree can see, and trees at least as tall blocking the view.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Rules {
+    directions: Vec<Direction>,
 
-            if visible {
-                total += 1;
-            }
+    /// How many trees a tree can see at most in any direction. A tree further than this from an
+    /// edge can't be seen from that edge either.
+    limit: Option<usize>,
+
+    blocking: Blocking,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            directions: Direction::CARDINAL.to_vec(),
+            limit: None,
+            blocking: Blocking::AtLeastAsTall,
+        }
+    }
+}
+
+/// The trees along a line so far that could still block the view of trees further along.
+///
+/// A tree hides every tree behind it that is no taller, so of the trees that are left each is
+/// shorter than all the ones behind it. That leaves at most one tree of each height, and the
+/// nearest one that is tall enough to block a view is always the shortest of those.
+#[derive(Debug, Clone, Copy, Default)]
+struct Blockers {
+    // Bit `h` is set if there is a tree of height `h`
+    heights: u16,
+
+    // Where the tree of each height is along the line
+    positions: [usize; HEIGHTS],
+}
+
+impl Blockers {
+    /// The position of the nearest tree at least `min_height` tall, if there is one.
+    fn nearest(&self, min_height: usize) -> Option<usize> {
+        let tall_enough = self.heights >> min_height;
+
+        match tall_enough {
+            0 => None,
+            _ => Some(self.positions[min_height + tall_enough.trailing_zeros() as usize]),
+        }
+    }
+
+    /// Adds the tree at position `i`, which hides every tree behind it that is no taller.
+    fn push(&mut self, i: usize, height: usize) {
+        self.heights = self.heights & !((2 << height) - 1) | 1 << height;
+        self.positions[height] = i;
+    }
+}
+
+impl Rules {
+    /// Moves one tree further along a line, given the trees that could still block its view,
+    /// and returns what the new tree can see back towards the edge.
+    fn look_back(&self, blockers: &mut Blockers, i: usize, tree_height: i32) -> View {
+        let height = tree_height as usize;
+        let blocker = match self.blocking {
+            Blocking::AtLeastAsTall => blockers.nearest(height),
+            Blocking::Taller => blockers.nearest(height + 1),
+        };
+
+        let limit = self.limit.unwrap_or(usize::MAX);
+        let view = match blocker {
+            Some(j) => View {
+                distance: (i - j).min(limit),
+                to_edge: false,
+            },
+            None => View {
+                distance: i.min(limit),
+                to_edge: i <= limit,
+            },
+        };
+
+        blockers.push(i, height);
+
+        view
+    }
+}
+
+/// What a tree can see looking in one direction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct View {
+    /// How many trees it can see, up to and including the first one blocking the view
+    distance: usize,
+
+    /// Whether it can see all the way to the edge, which means it's visible from there
+    to_edge: bool,
+}
+
+/// A value for every tree in the grid, indexed by `[y][x]`.
+type Map<T> = Vec<Vec<T>>;
+
+/// Walks every line of trees looking `direction`, starting from the edge it looks towards, and
+/// calls `f` with each row number and what every tree in that row can see.
+///
+/// Rows are visited in order whichever way the lines run, so lines that cross rows are walked
+/// side by side, each keeping its own blockers.
+fn walk_views(grid: &Grid, rules: &Rules, direction: Direction, mut f: impl FnMut(usize, &[View])) {
+    let width = grid.width() as usize;
+    let height = grid.height() as usize;
+    let (dx, dy) = direction.step();
+
+    // Trees on the same line share a number: the column for lines running up and down, and the
+    // column shifted by the row for diagonals, offset so it's never negative. Lines along a row
+    // are only ever walked one at a time
+    let (lines, column) = match (dx, dy) {
+        (_, 0) => (1, 0),
+        (0, _) => (width, 1),
+        _ => (width + height - 1, 1),
+    };
+    let row_offset = |y: usize| match (dx, dy) {
+        (_, 0) | (0, _) => 0,
+        _ if dx == dy => height - 1 - y,
+        _ => y,
+    };
+    let mut blockers = vec![Blockers::default(); lines];
+    let mut views = vec![View::default(); width];
+
+    for k in 0..height {
+        let y = if dy > 0 { height - 1 - k } else { k };
+        let to_y_edge = match dy {
+            0 => usize::MAX,
+            _ => k,
+        };
+
+        // Lines along the row start again on every row
+        if dy == 0 {
+            blockers[0] = Blockers::default();
+        }
+
+        let (row, offset) = (&grid.trees[y], row_offset(y));
+        for j in 0..width {
+            let x = if dx > 0 { width - 1 - j } else { j };
+            let i = match dx {
+                0 => to_y_edge,
+                _ => j.min(to_y_edge),
+            };
+
+            let line = &mut blockers[x * column + offset];
+            views[x] = rules.look_back(line, i, row[x].height);
         }
+
+        f(y, &views);
     }
+}
+
+/// Works out what every tree can see, with one map for each of the directions in `rules`.
+fn views(grid: &Grid, rules: &Rules) -> Vec<Map<View>> {
+    rules
+        .directions
+        .iter()
+        .map(|&direction| {
+            let mut views =
+                vec![vec![View::default(); grid.width() as usize]; grid.height() as usize];
+            walk_views(grid, rules, direction, |y, row| {
+                views[y].copy_from_slice(row)
+            });
+
+            views
+        })
+        .collect()
+}
+
+/// Combines the views in every direction into a single value for each tree, starting from `init`
+/// and folding in one view at a time.
+fn combine_views<T: Clone>(
+    grid: &Grid,
+    rules: &Rules,
+    init: T,
+    f: impl Fn(&mut T, View),
+) -> Map<T> {
+    let mut combined = vec![vec![init; grid.width() as usize]; grid.height() as usize];
+
+    for &direction in &rules.directions {
+        walk_views(grid, rules, direction, |y, views| {
+            for (value, &view) in combined[y].iter_mut().zip(views) {
+                f(value, view);
+            }
+        });
+    }
+
+    combined
+}
+
+/// Whether each tree can be seen from outside the grid.
+fn visibility_map(grid: &Grid, rules: &Rules) -> Map<bool> {
+    combine_views(grid, rules, false, |visible, view| *visible |= view.to_edge)
+}
 
-    Ok(total)
+/// The scenic score of each tree, which is the product of its viewing distances.
+fn scenic_map(grid: &Grid, rules: &Rules) -> Map<u64> {
+    combine_views(grid, rules, 1, |score, view| *score *= view.distance as u64)
 }
 
-fn part2(input: &str) -> Result<i32> {
+/// A tree with a good view, along with how far it can see in each direction.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Spot {
+    x: usize,
+    y: usize,
+    score: u64,
+    distances: Vec<(Direction, usize)>,
+}
+
+/// The `count` trees with the highest scenic scores, best first. Ties go to the tree that
+/// [`best_location`] would pick.
+fn top_spots(grid: &Grid, rules: &Rules, count: usize) -> Vec<Spot> {
+    let views = views(grid, rules);
+    let score =
+        |x: usize, y: usize| -> u64 { views.iter().map(|map| map[y][x].distance as u64).product() };
+
+    let mut spots = (0..grid.width() as usize)
+        .flat_map(|x| (0..grid.height() as usize).map(move |y| (x, y)))
+        .map(|(x, y)| (score(x, y), x, y))
+        .collect::<Vec<_>>();
+    spots.sort_by(|a, b| b.0.cmp(&a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+
+    spots
+        .into_iter()
+        .take(count)
+        .map(|(score, x, y)| Spot {
+            x,
+            y,
+            score,
+            distances: rules
+                .directions
+                .iter()
+                .zip(&views)
+                .map(|(&direction, map)| (direction, map[y][x].distance))
+                .collect(),
+        })
+        .collect()
+}
+
+fn part1(input: &str) -> Result<usize> {
     let grid = Grid::new(input)?;
 
-    let width = grid.width();
-    let height = grid.height();
+    Ok(visibility_map(&grid, &Rules::default())
+        .iter()
+        .flatten()
+        .filter(|&&visible| visible)
+        .count())
+}
 
-    let mut score = 0;
-    let mut selected = "".to_string();
+/// The tree with the best scenic score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Location {
+    x: usize,
+    y: usize,
+    score: u64,
+}
+
+/// Finds the tree with the highest scenic score. Ties go to the first one going down each column
+/// in turn, as they always have.
+fn best_location(scenic: &Map<u64>) -> Option<Location> {
+    let width = scenic.first().map_or(0, |row| row.len());
+    let mut best: Option<Location> = None;
 
     for x in 0..width {
-        for y in 0..height {
-            let item = grid.get(x, y).context("Failed to get grid item.")?;
-            let tree_height = item.height;
+        for (y, row) in scenic.iter().enumerate() {
+            if best.is_none_or(|best| row[x] > best.score) {
+                best = Some(Location {
+                    x,
+                    y,
+                    score: row[x],
+                });
+            }
+        }
+    }
+
+    best
+}
+
+/// Draws the forest with visible trees as their height and hidden trees as `.`, marking the
+/// most scenic tree with `*`.
+fn render_visibility(grid: &Grid) -> String {
+    let rules = Rules::default();
+    let visible = visibility_map(grid, &rules);
+    let best = best_location(&scenic_map(grid, &rules));
+
+    let mut output = String::new();
+    for (y, row) in grid.trees.iter().enumerate() {
+        for (x, tree) in row.iter().enumerate() {
+            let c = match best {
+                Some(best) if (best.x, best.y) == (x, y) => '*',
+                _ if visible[y][x] => char::from(b'0' + tree.height as u8),
+                _ => '.',
+            };
+            output.push(c);
+        }
 
-            // Left
-            let left = check_x_distance(&grid, (0..x).rev(), y, tree_height);
+        output.push('\n');
+    }
 
-            // Right
-            let right = check_x_distance(&grid, (x + 1)..width, y, tree_height);
+    output
+}
 
-            // Up
-            let up = check_y_distance(&grid, (0..y).rev(), x, tree_height);
+/// The ANSI 256-colour palette from cold to hot, used for scenic scores.
+const HEAT_RAMP: [u8; 16] = [
+    17, 18, 19, 20, 21, 27, 33, 39, 45, 51, 49, 47, 46, 226, 208, 196,
+];
 
-            // Down
-            let down = check_y_distance(&grid, (y + 1)..height, x, tree_height);
+/// Scales scenic scores to `0.0..=1.0`. Scores grow as a product of four distances, so a few
+/// trees dwarf everything else and a logarithmic scale shows much more.
+fn heat_levels(scenic: &Map<u64>) -> Map<f64> {
+    let max = scenic.iter().flatten().copied().max().unwrap_or(0);
+    let scale = (1.0 + max as f64).ln();
 
-            let total = left * right * up * down;
+    scenic
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|&score| match max {
+                    0 => 0.0,
+                    _ => (1.0 + score as f64).ln() / scale,
+                })
+                .collect()
+        })
+        .collect()
+}
 
-            if total > score {
-                score = total;
-                selected = format!("({}, {})", x, y);
-            }
+fn heat_colour(level: f64) -> u8 {
+    let i = (level * (HEAT_RAMP.len() - 1) as f64).round() as usize;
+
+    HEAT_RAMP[i.min(HEAT_RAMP.len() - 1)]
+}
+
+/// The RGB value a terminal uses for an ANSI 256-colour code. The first 16 codes are up to the
+/// terminal, so these use the xterm defaults.
+fn ansi_to_rgb(code: u8) -> [u8; 3] {
+    const BASIC: [[u8; 3]; 16] = [
+        [0, 0, 0],
+        [205, 0, 0],
+        [0, 205, 0],
+        [205, 205, 0],
+        [0, 0, 238],
+        [205, 0, 205],
+        [0, 205, 205],
+        [229, 229, 229],
+        [127, 127, 127],
+        [255, 0, 0],
+        [0, 255, 0],
+        [255, 255, 0],
+        [92, 92, 255],
+        [255, 0, 255],
+        [0, 255, 255],
+        [255, 255, 255],
+    ];
+    const CUBE: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+    match code {
+        0..=15 => BASIC[code as usize],
+        16..=231 => {
+            let i = code - 16;
+            [
+                CUBE[(i / 36) as usize],
+                CUBE[(i / 6 % 6) as usize],
+                CUBE[(i % 6) as usize],
+            ]
+        }
+        _ => {
+            let grey = 8 + 10 * (code - 232);
+            [grey, grey, grey]
         }
     }
+}
+
+/// Draws the forest with each tree's height on a background coloured by its scenic score, using
+/// ANSI 256-colour escapes.
+fn render_heatmap(grid: &Grid) -> String {
+    let levels = heat_levels(&scenic_map(grid, &Rules::default()));
 
-    Ok(score)
+    let mut output = String::new();
+    for (row, levels) in grid.trees.iter().zip(&levels) {
+        for (tree, &level) in row.iter().zip(levels) {
+            output.push_str(&format!("\x1b[48;5;{}m{}", heat_colour(level), tree.height));
+        }
+
+        output.push_str("\x1b[0m\n");
+    }
+
+    output
+}
+
+/// Writes a binary PGM image of the forest with visible trees in white and hidden ones in black.
+fn write_visibility_pgm(grid: &Grid, out: &mut impl Write) -> Result<()> {
+    let visible = visibility_map(grid, &Rules::default());
+
+    write!(out, "P5\n{} {}\n255\n", grid.width(), grid.height())?;
+    for row in visible {
+        let pixels = row
+            .into_iter()
+            .map(|visible| if visible { 255 } else { 0 })
+            .collect::<Vec<u8>>();
+        out.write_all(&pixels)?;
+    }
+
+    Ok(())
+}
+
+/// Writes a binary PPM image of the scenic score heatmap, in the same colours as
+/// [`render_heatmap`].
+fn write_heatmap_ppm(grid: &Grid, out: &mut impl Write) -> Result<()> {
+    let levels = heat_levels(&scenic_map(grid, &Rules::default()));
+
+    write!(out, "P6\n{} {}\n255\n", grid.width(), grid.height())?;
+    for row in levels {
+        let pixels = row
+            .into_iter()
+            .flat_map(|level| ansi_to_rgb(heat_colour(level)))
+            .collect::<Vec<u8>>();
+        out.write_all(&pixels)?;
+    }
+
+    Ok(())
+}
+
+fn part2(input: &str) -> Result<u64> {
+    let grid = Grid::new(input)?;
+
+    let best =
+        best_location(&scenic_map(&grid, &Rules::default())).context("Grid has no trees.")?;
+
+    Ok(best.score)
 }
 
 #[cfg(test)]
@@ -205,7 +634,7 @@ mod tests_example {
 
     use crate::util;
 
-    const INPUT: &str = indoc! {"
+    pub(super) const INPUT: &str = indoc! {"
         30373
         25512
         65332
@@ -230,4 +659,417 @@ mod tests_example {
 
         Ok(())
     }
+
+    #[test]
+    fn test_ragged_input() {
+        assert!(super::part1("30373\n2551\n65332").is_err());
+        assert!(super::part2("30373\n25512\n").is_err());
+        assert!(super::part1("").is_err());
+    }
+}
+
+#[cfg(test)]
+mod tests_linear {
+    use std::time::{Duration, Instant};
+
+    use anyhow::Result;
+
+    use super::{Grid, Rules};
+    use crate::util::rng::Rng;
+
+    pub(super) fn random_forest(rng: &mut Rng, width: usize, height: usize) -> String {
+        (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|_| char::from(b'0' + rng.range(0, 10) as u8))
+                    .collect::<String>()
+            })
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// The original scan in every direction from every tree.
+    fn naive_visible(grid: &Grid, x: i32, y: i32) -> bool {
+        let tree_height = grid.get(x, y).unwrap().height;
+
+        super::check_x_range(grid, 0..x, y, tree_height)
+            || super::check_x_range(grid, (x + 1)..grid.width(), y, tree_height)
+            || super::check_y_range(grid, 0..y, x, tree_height)
+            || super::check_y_range(grid, (y + 1)..grid.height(), x, tree_height)
+    }
+
+    fn naive_scenic(grid: &Grid, x: i32, y: i32) -> u64 {
+        let tree_height = grid.get(x, y).unwrap().height;
+
+        let score = super::check_x_distance(grid, (0..x).rev(), y, tree_height)
+            * super::check_x_distance(grid, (x + 1)..grid.width(), y, tree_height)
+            * super::check_y_distance(grid, (0..y).rev(), x, tree_height)
+            * super::check_y_distance(grid, (y + 1)..grid.height(), x, tree_height);
+
+        score as u64
+    }
+
+    #[test]
+    fn test_matches_naive() -> Result<()> {
+        let mut rng = Rng::new(8);
+
+        for _ in 0..300 {
+            let width = rng.range(1, 15) as usize;
+            let height = rng.range(1, 15) as usize;
+            let grid = Grid::new(&random_forest(&mut rng, width, height))?;
+
+            let visible = super::visibility_map(&grid, &Rules::default());
+            let scenic = super::scenic_map(&grid, &Rules::default());
+
+            for y in 0..grid.height() {
+                for x in 0..grid.width() {
+                    let (i, j) = (y as usize, x as usize);
+
+                    assert_eq!(visible[i][j], naive_visible(&grid, x, y), "({}, {})", x, y);
+                    assert_eq!(scenic[i][j], naive_scenic(&grid, x, y), "({}, {})", x, y);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs `f` a few times, returning its result and the fastest run, which is the one least
+    /// disturbed by whatever else the machine is doing.
+    fn fastest<T>(mut f: impl FnMut() -> T) -> (T, Duration) {
+        let mut best = Duration::MAX;
+        let mut result = None;
+
+        for _ in 0..3 {
+            let start = Instant::now();
+            result = Some(f());
+            best = best.min(start.elapsed());
+        }
+
+        (result.unwrap(), best)
+    }
+
+    #[test]
+    #[ignore]
+    fn bench_large_forest() -> Result<()> {
+        let input = random_forest(&mut Rng::new(1000), 1000, 1000);
+        let grid = Grid::new(&input)?;
+        let (width, height) = (grid.width(), grid.height());
+
+        let (visible, visible_time) = fastest(|| super::visibility_map(&grid, &Rules::default()));
+        println!("visibility map in {:?}", visible_time);
+
+        let (naive_visible_count, naive_visible_time) = fastest(|| {
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .filter(|&(x, y)| naive_visible(&grid, x, y))
+                .count()
+        });
+        println!("naive visibility in {:?}", naive_visible_time);
+
+        let (scenic, scenic_time) = fastest(|| super::scenic_map(&grid, &Rules::default()));
+        println!("scenic map in {:?}", scenic_time);
+
+        let (naive_best, naive_scenic_time) = fastest(|| {
+            (0..height)
+                .flat_map(|y| (0..width).map(move |x| (x, y)))
+                .map(|(x, y)| naive_scenic(&grid, x, y))
+                .max()
+        });
+        println!("naive scenic scores in {:?}", naive_scenic_time);
+
+        assert_eq!(
+            visible.iter().flatten().filter(|&&v| v).count(),
+            naive_visible_count
+        );
+        assert_eq!(scenic.into_iter().flatten().max(), naive_best);
+
+        assert!(
+            visible_time < naive_visible_time,
+            "visibility map is slower than the naive scan"
+        );
+        assert!(
+            scenic_time < naive_scenic_time,
+            "scenic map is slower than the naive scan"
+        );
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests_render {
+    use anyhow::Result;
+    use indoc::indoc;
+
+    use super::{tests_example::INPUT, Grid, Location, Rules};
+    use crate::util;
+
+    #[test]
+    fn test_best_location() -> Result<()> {
+        let grid = Grid::new(util::format_input(INPUT))?;
+
+        assert_eq!(
+            super::best_location(&super::scenic_map(&grid, &Rules::default())),
+            Some(Location {
+                x: 2,
+                y: 3,
+                score: 8
+            })
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_visibility() -> Result<()> {
+        let grid = Grid::new(util::format_input(INPUT))?;
+
+        let expected = indoc! {"
+            30373
+            255.2
+            65.32
+            3.*.9
+            35390
+        "};
+
+        assert_eq!(super::render_visibility(&grid), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_heatmap() -> Result<()> {
+        let grid = Grid::new(util::format_input(INPUT))?;
+        let rendered = super::render_heatmap(&grid);
+
+        let lines = rendered.lines().collect::<Vec<&str>>();
+        assert_eq!(lines.len(), 5);
+        assert!(lines.iter().all(|line| line.ends_with("\x1b[0m")));
+
+        // Edge trees score nothing and the best tree is as hot as it gets
+        assert!(lines[0].starts_with("\x1b[48;5;17m3\x1b[48;5;17m0"));
+        assert!(lines[3].contains("\x1b[48;5;196m5"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_images() -> Result<()> {
+        let grid = Grid::new(util::format_input(INPUT))?;
+
+        let mut pgm = Vec::new();
+        super::write_visibility_pgm(&grid, &mut pgm)?;
+        let header = b"P5\n5 5\n255\n";
+        assert!(pgm.starts_with(header));
+        assert_eq!(&pgm[header.len()..][5..10], &[255, 255, 255, 0, 255]);
+
+        let mut ppm = Vec::new();
+        super::write_heatmap_ppm(&grid, &mut ppm)?;
+        let header = b"P6\n5 5\n255\n";
+        assert!(ppm.starts_with(header));
+        assert_eq!(ppm.len(), header.len() + 5 * 5 * 3);
+
+        let best = header.len() + (3 * 5 + 2) * 3;
+        assert_eq!(&ppm[best..best + 3], &[255, 0, 0]);
+        assert_eq!(&ppm[header.len()..header.len() + 3], &[0, 0, 95]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_ansi_to_rgb() {
+        assert_eq!(super::ansi_to_rgb(9), [255, 0, 0]);
+        assert_eq!(super::ansi_to_rgb(16), [0, 0, 0]);
+        assert_eq!(super::ansi_to_rgb(208), [255, 135, 0]);
+        assert_eq!(super::ansi_to_rgb(231), [255, 255, 255]);
+        assert_eq!(super::ansi_to_rgb(244), [128, 128, 128]);
+    }
+}
+
+#[cfg(test)]
+mod tests_rules {
+    use anyhow::Result;
+
+    use super::{
+        tests_example::INPUT, tests_linear::random_forest, Blocking, Direction, Grid, Rules, Spot,
+        View,
+    };
+    use crate::util::{self, rng::Rng};
+
+    /// Walks out from a tree one step at a time until something blocks the view.
+    fn naive_view(grid: &Grid, rules: &Rules, direction: Direction, x: i32, y: i32) -> View {
+        let tree_height = grid.get(x, y).unwrap().height;
+        let limit = rules.limit.unwrap_or(usize::MAX);
+        let (dx, dy) = direction.step();
+
+        let mut distance = 0;
+        let (mut x, mut y) = (x + dx, y + dy);
+
+        while let Some(other) = grid.get(x, y).filter(|_| x >= 0 && y >= 0) {
+            if distance == limit {
+                return View {
+                    distance,
+                    to_edge: false,
+                };
+            }
+
+            distance += 1;
+
+            let blocked = match rules.blocking {
+                Blocking::AtLeastAsTall => other.height >= tree_height,
+                Blocking::Taller => other.height > tree_height,
+            };
+            if blocked {
+                return View {
+                    distance,
+                    to_edge: false,
+                };
+            }
+
+            (x, y) = (x + dx, y + dy);
+        }
+
+        View {
+            distance,
+            to_edge: true,
+        }
+    }
+
+    #[test]
+    fn test_matches_naive() -> Result<()> {
+        let mut rng = Rng::new(50);
+
+        for _ in 0..300 {
+            let width = rng.range(1, 12) as usize;
+            let height = rng.range(1, 12) as usize;
+            let grid = Grid::new(&random_forest(&mut rng, width, height))?;
+
+            let rules = Rules {
+                directions: if rng.range(0, 2) == 0 {
+                    Direction::CARDINAL.to_vec()
+                } else {
+                    Direction::ALL.to_vec()
+                },
+                limit: match rng.range(0, 3) {
+                    0 => None,
+                    _ => Some(rng.range(0, 6) as usize),
+                },
+                blocking: if rng.range(0, 2) == 0 {
+                    Blocking::AtLeastAsTall
+                } else {
+                    Blocking::Taller
+                },
+            };
+
+            let views = super::views(&grid, &rules);
+
+            for (direction, map) in rules.directions.iter().zip(&views) {
+                for y in 0..grid.height() {
+                    for x in 0..grid.width() {
+                        assert_eq!(
+                            map[y as usize][x as usize],
+                            naive_view(&grid, &rules, *direction, x, y),
+                            "{:?} from ({}, {}) with {:?}",
+                            direction,
+                            x,
+                            y,
+                            rules
+                        );
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_rules() -> Result<()> {
+        let grid = Grid::new("33333")?;
+
+        // A single row can be seen from above and below, so only look along it
+        let along = Rules {
+            directions: vec![Direction::Left, Direction::Right],
+            ..Rules::default()
+        };
+        let taller = Rules {
+            blocking: Blocking::Taller,
+            ..along.clone()
+        };
+        let limited = Rules {
+            limit: Some(1),
+            ..taller.clone()
+        };
+
+        assert_eq!(
+            super::visibility_map(&grid, &along),
+            vec![vec![true, false, false, false, true]]
+        );
+        assert_eq!(super::visibility_map(&grid, &taller), vec![vec![true; 5]]);
+        assert_eq!(
+            super::visibility_map(&grid, &limited),
+            vec![vec![true, true, false, true, true]]
+        );
+
+        assert_eq!(super::scenic_map(&grid, &along), vec![vec![0, 1, 1, 1, 0]]);
+        assert_eq!(super::scenic_map(&grid, &taller), vec![vec![0, 3, 4, 3, 0]]);
+        assert_eq!(
+            super::scenic_map(&grid, &limited),
+            vec![vec![0, 1, 1, 1, 0]]
+        );
+
+        // Nothing to see up or down from a single row
+        assert_eq!(
+            super::scenic_map(&grid, &Rules::default()),
+            vec![vec![0; 5]]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_top_spots() -> Result<()> {
+        let grid = Grid::new(util::format_input(INPUT))?;
+
+        let spots = super::top_spots(&grid, &Rules::default(), 3);
+        assert_eq!(
+            spots[0],
+            Spot {
+                x: 2,
+                y: 3,
+                score: 8,
+                distances: vec![
+                    (Direction::Left, 2),
+                    (Direction::Right, 2),
+                    (Direction::Up, 2),
+                    (Direction::Down, 1),
+                ],
+            }
+        );
+        assert_eq!(spots.len(), 3);
+        assert!(spots.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        // Looking diagonally as well, scores are the product of all eight distances
+        let rules = Rules {
+            directions: Direction::ALL.to_vec(),
+            ..Rules::default()
+        };
+        let spots = super::top_spots(&grid, &rules, 1);
+        let scenic = super::scenic_map(&grid, &rules);
+        assert_eq!(Some(spots[0].score), scenic.iter().flatten().copied().max());
+        assert_eq!(spots[0].distances.len(), 8);
+        assert_eq!(
+            spots[0].score,
+            spots[0]
+                .distances
+                .iter()
+                .map(|&(_, distance)| distance as u64)
+                .product::<u64>()
+        );
+
+        assert_eq!(super::top_spots(&grid, &rules, 100).len(), 25);
+
+        Ok(())
+    }
 }
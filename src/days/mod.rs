@@ -4,7 +4,7 @@ mod day03;
 mod day04;
 mod day05;
 mod day06;
-mod day07;
+pub(crate) mod day07;
 mod day08;
 mod day09;
 mod day10;
@@ -1,2 +1,33 @@
 mod days;
 mod util;
+
+use std::io;
+
+use anyhow::{bail, Result};
+
+use days::day07;
+
+const USAGE: &str = "\
+usage:
+  aoc day07 --report tree|du|top[=N]    print a report of the filesystem in the transcript
+  aoc day07 --shell                     explore the filesystem interactively";
+
+/// Runs the tool named by the command line arguments (not including the program name) against
+/// that day's puzzle input.
+pub fn run(args: &[String]) -> Result<()> {
+    let args = args.iter().map(String::as_str).collect::<Vec<&str>>();
+
+    match args.as_slice() {
+        ["day07", "--report", report] => {
+            let report = report.parse::<day07::Report>()?;
+
+            print!("{}", day07::report(&util::read_input(7)?, &report)?);
+        }
+        ["day07", "--shell"] => {
+            day07::shell(&util::read_input(7)?, io::stdin().lock(), io::stdout())?;
+        }
+        _ => bail!(USAGE),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,5 @@
+use anyhow::Result;
+
+fn main() -> Result<()> {
+    aoc2022::run(&std::env::args().skip(1).collect::<Vec<String>>())
+}
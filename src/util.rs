@@ -1,3 +1,8 @@
+pub mod interval;
+pub mod rng;
+#[cfg(test)]
+pub mod temp_dir;
+
 use anyhow::Result;
 use std::fs;
 
@@ -0,0 +1,453 @@
+use std::{
+    fmt::Debug,
+    iter::Sum,
+    ops::{Range, Sub},
+};
+
+/// A half-open interval `[start, end)`, like [`Range`], but with O(1) set operations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Interval<T> {
+    start: T,
+    end: T,
+}
+
+impl<T: Copy + Ord> Interval<T> {
+    /// Creates the interval `[start, end)`. If `end` is before `start`, the interval is empty.
+    pub fn new(start: T, end: T) -> Self {
+        Interval {
+            start,
+            end: end.max(start),
+        }
+    }
+
+    pub fn start(&self) -> T {
+        self.start
+    }
+
+    pub fn end(&self) -> T {
+        self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        self.start <= *value && *value < self.end
+    }
+
+    /// Returns true if `other` lies entirely within this interval. Empty intervals are never
+    /// considered to be contained.
+    pub fn contains_interval(&self, other: &Interval<T>) -> bool {
+        !other.is_empty() && self.start <= other.start && other.end <= self.end
+    }
+
+    /// Returns true if the two intervals share at least one value.
+    pub fn overlaps(&self, other: &Interval<T>) -> bool {
+        self.start < other.end && other.start < self.end
+    }
+
+    /// Returns true if the two intervals overlap or sit directly next to each other, meaning
+    /// their union is a single interval.
+    pub fn touches(&self, other: &Interval<T>) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    pub fn intersection(&self, other: &Interval<T>) -> Option<Interval<T>> {
+        if !self.overlaps(other) {
+            return None;
+        }
+
+        Some(Interval::new(
+            self.start.max(other.start),
+            self.end.min(other.end),
+        ))
+    }
+
+    /// Returns the union of the two intervals, or `None` if there is a gap between them.
+    pub fn union(&self, other: &Interval<T>) -> Option<Interval<T>> {
+        if self.is_empty() {
+            return Some(*other);
+        }
+
+        if other.is_empty() {
+            return Some(*self);
+        }
+
+        if !self.touches(other) {
+            return None;
+        }
+
+        Some(Interval::new(
+            self.start.min(other.start),
+            self.end.max(other.end),
+        ))
+    }
+}
+
+impl<T: Copy + Ord + Sub<Output = T>> Interval<T> {
+    pub fn len(&self) -> T {
+        self.end - self.start
+    }
+}
+
+impl<T: Copy + Ord> From<Range<T>> for Interval<T> {
+    fn from(range: Range<T>) -> Self {
+        Interval::new(range.start, range.end)
+    }
+}
+
+/// A set of values stored as sorted, non-overlapping intervals. Overlapping or adjacent
+/// intervals are merged on insertion.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IntervalSet<T> {
+    intervals: Vec<Interval<T>>,
+}
+
+impl<T: Copy + Ord> IntervalSet<T> {
+    pub fn new() -> Self {
+        IntervalSet {
+            intervals: Vec::new(),
+        }
+    }
+
+    pub fn insert(&mut self, interval: Interval<T>) {
+        if interval.is_empty() {
+            return;
+        }
+
+        // Everything in [first, last) touches the new interval and gets merged into it
+        let first = self
+            .intervals
+            .partition_point(|existing| existing.end < interval.start);
+        let last = self
+            .intervals
+            .partition_point(|existing| existing.start <= interval.end);
+
+        let merged = self.intervals[first..last]
+            .iter()
+            .fold(interval, |acc, existing| acc.union(existing).unwrap());
+
+        self.intervals.splice(first..last, [merged]);
+    }
+
+    pub fn remove(&mut self, interval: Interval<T>) {
+        if interval.is_empty() {
+            return;
+        }
+
+        let first = self
+            .intervals
+            .partition_point(|existing| existing.end <= interval.start);
+        let last = self
+            .intervals
+            .partition_point(|existing| existing.start < interval.end);
+
+        let mut remaining = Vec::new();
+        for existing in &self.intervals[first..last] {
+            if existing.start < interval.start {
+                remaining.push(Interval::new(existing.start, interval.start));
+            }
+
+            if interval.end < existing.end {
+                remaining.push(Interval::new(interval.end, existing.end));
+            }
+        }
+
+        self.intervals.splice(first..last, remaining);
+    }
+
+    pub fn contains(&self, value: &T) -> bool {
+        let i = self
+            .intervals
+            .partition_point(|existing| existing.end <= *value);
+
+        self.intervals
+            .get(i)
+            .is_some_and(|existing| existing.contains(value))
+    }
+
+    /// Returns the parts of `bounds` that are not covered by this set.
+    pub fn complement(&self, bounds: Interval<T>) -> IntervalSet<T> {
+        let mut complement = IntervalSet::new();
+        complement.insert(bounds);
+
+        for interval in &self.intervals {
+            complement.remove(*interval);
+        }
+
+        complement
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.intervals.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Interval<T>> {
+        self.intervals.iter()
+    }
+}
+
+impl<T: Copy + Ord + Sub<Output = T> + Sum> IntervalSet<T> {
+    /// The total number of values covered by the set.
+    pub fn len(&self) -> T {
+        self.intervals.iter().map(|interval| interval.len()).sum()
+    }
+}
+
+impl<T: Copy + Ord> Default for IntervalSet<T> {
+    fn default() -> Self {
+        IntervalSet::new()
+    }
+}
+
+impl<T: Copy + Ord> FromIterator<Interval<T>> for IntervalSet<T> {
+    fn from_iter<I: IntoIterator<Item = Interval<T>>>(iter: I) -> Self {
+        let mut set = IntervalSet::new();
+
+        for interval in iter {
+            set.insert(interval);
+        }
+
+        set
+    }
+}
+
+/// A static index over a list of intervals, answering overlap and stabbing queries in
+/// O(log n + k) time. Results are given as indices into the original list.
+///
+/// Internally this is an implicit interval tree: intervals are sorted by start, and each node of
+/// the balanced tree over that array stores the furthest end within its subtree.
+#[derive(Debug, Clone)]
+pub struct IntervalIndex<T> {
+    intervals: Vec<(Interval<T>, usize)>,
+    max_end: Vec<T>,
+}
+
+impl<T: Copy + Ord> IntervalIndex<T> {
+    pub fn new(intervals: &[Interval<T>]) -> Self {
+        // Empty intervals can never match a query, so leave them out entirely
+        let mut sorted = intervals
+            .iter()
+            .copied()
+            .enumerate()
+            .filter(|(_, interval)| !interval.is_empty())
+            .map(|(i, interval)| (interval, i))
+            .collect::<Vec<(Interval<T>, usize)>>();
+        sorted.sort_by_key(|(interval, i)| (interval.start, interval.end, *i));
+
+        let max_end = sorted.iter().map(|(interval, _)| interval.end).collect();
+
+        let mut index = IntervalIndex {
+            intervals: sorted,
+            max_end,
+        };
+        index.build(0, index.intervals.len());
+
+        index
+    }
+
+    fn build(&mut self, lo: usize, hi: usize) -> Option<T> {
+        if lo >= hi {
+            return None;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        let left = self.build(lo, mid);
+        let right = self.build(mid + 1, hi);
+
+        let max_end = [left, right]
+            .into_iter()
+            .flatten()
+            .fold(self.max_end[mid], T::max);
+        self.max_end[mid] = max_end;
+
+        Some(max_end)
+    }
+
+    /// Visits every interval with an end after `after` and a start accepted by `start_ok`, which
+    /// must only ever go from true to false as the start increases.
+    fn search(
+        &self,
+        lo: usize,
+        hi: usize,
+        after: T,
+        start_ok: &dyn Fn(T) -> bool,
+        found: &mut Vec<usize>,
+    ) {
+        if lo >= hi {
+            return;
+        }
+
+        let mid = lo + (hi - lo) / 2;
+        if self.max_end[mid] <= after {
+            return;
+        }
+
+        self.search(lo, mid, after, start_ok, found);
+
+        let (interval, i) = self.intervals[mid];
+        if !start_ok(interval.start) {
+            return;
+        }
+
+        if interval.end > after {
+            found.push(i);
+        }
+
+        self.search(mid + 1, hi, after, start_ok, found);
+    }
+
+    /// Returns every interval that shares at least one value with `query`.
+    pub fn overlapping(&self, query: Interval<T>) -> Vec<usize> {
+        let mut found = Vec::new();
+
+        if !query.is_empty() {
+            let start_ok = |start: T| start < query.end;
+            self.search(0, self.intervals.len(), query.start, &start_ok, &mut found);
+        }
+
+        found.sort();
+        found
+    }
+
+    /// Returns every interval that contains `point`.
+    pub fn containing(&self, point: T) -> Vec<usize> {
+        let mut found = Vec::new();
+
+        let start_ok = |start: T| start <= point;
+        self.search(0, self.intervals.len(), point, &start_ok, &mut found);
+
+        found.sort();
+        found
+    }
+
+    /// Counts the pairs of intervals in the index that overlap each other.
+    pub fn overlapping_pairs(&self) -> usize {
+        let n = self.intervals.len();
+
+        let mut ends = self
+            .intervals
+            .iter()
+            .map(|(interval, _)| interval.end)
+            .collect::<Vec<T>>();
+        ends.sort();
+
+        // Two intervals don't overlap exactly when one ends before the other starts, which can
+        // only happen one way round for any given pair
+        let disjoint: usize = self
+            .intervals
+            .iter()
+            .map(|(interval, _)| ends.partition_point(|end| *end <= interval.start))
+            .sum();
+
+        n * n.saturating_sub(1) / 2 - disjoint
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interval, IntervalIndex, IntervalSet};
+
+    #[test]
+    fn test_interval() {
+        let a = Interval::new(2, 5);
+        let b = Interval::new(4, 9);
+        let c = Interval::new(5, 7);
+
+        assert!(a.contains(&2));
+        assert!(!a.contains(&5));
+        assert_eq!(a.len(), 3);
+
+        assert!(a.overlaps(&b));
+        assert!(!a.overlaps(&c));
+        assert!(b.contains_interval(&c));
+        assert!(!c.contains_interval(&b));
+        assert!(!a.contains_interval(&Interval::new(3, 3)));
+
+        assert_eq!(a.intersection(&b), Some(Interval::new(4, 5)));
+        assert_eq!(a.intersection(&c), None);
+        assert_eq!(a.union(&c), Some(Interval::new(2, 7)));
+        assert_eq!(a.union(&Interval::new(6, 7)), None);
+
+        // A check interval that strictly encloses the base still overlaps it
+        let outer = Interval::new(0, 10);
+        assert!(a.overlaps(&outer));
+        assert!(outer.overlaps(&a));
+    }
+
+    #[test]
+    fn test_set_insert_merges() {
+        let set = [
+            Interval::new(1, 3),
+            Interval::new(10, 12),
+            Interval::new(5, 7),
+            Interval::new(3, 4),
+            Interval::new(6, 11),
+        ]
+        .into_iter()
+        .collect::<IntervalSet<u32>>();
+
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![Interval::new(1, 4), Interval::new(5, 12)]
+        );
+        assert_eq!(set.len(), 10);
+        assert!(set.contains(&3));
+        assert!(!set.contains(&4));
+        assert!(set.contains(&11));
+        assert!(!set.contains(&12));
+    }
+
+    #[test]
+    fn test_set_remove_and_complement() {
+        let mut set = IntervalSet::new();
+        set.insert(Interval::new(0, 10));
+        set.insert(Interval::new(20, 30));
+
+        set.remove(Interval::new(5, 25));
+        assert_eq!(
+            set.iter().copied().collect::<Vec<_>>(),
+            vec![Interval::new(0, 5), Interval::new(25, 30)]
+        );
+
+        set.remove(Interval::new(2, 3));
+        assert_eq!(set.len(), 9);
+
+        let complement = set.complement(Interval::new(1, 40));
+        assert_eq!(
+            complement.iter().copied().collect::<Vec<_>>(),
+            vec![
+                Interval::new(2, 3),
+                Interval::new(5, 25),
+                Interval::new(30, 40)
+            ]
+        );
+        assert_eq!(complement.len(), 31);
+
+        assert!(!set.is_empty());
+        set.remove(Interval::new(0, 30));
+        assert!(set.is_empty());
+    }
+
+    #[test]
+    fn test_index() {
+        let intervals = [
+            Interval::new(1, 4),
+            Interval::new(3, 6),
+            Interval::new(8, 8),
+            Interval::new(0, 10),
+            Interval::new(6, 7),
+        ];
+        let index = IntervalIndex::new(&intervals);
+
+        assert_eq!(index.overlapping(Interval::new(4, 6)), vec![1, 3]);
+        assert_eq!(index.overlapping(Interval::new(6, 6)), Vec::<usize>::new());
+        assert_eq!(index.containing(3), vec![0, 1, 3]);
+        assert_eq!(index.containing(8), vec![3]);
+        assert_eq!(index.containing(10), Vec::<usize>::new());
+
+        // (0, 1), (0, 3), (1, 3), (3, 4)
+        assert_eq!(index.overlapping_pairs(), 4);
+    }
+}
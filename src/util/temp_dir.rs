@@ -0,0 +1,36 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process,
+    time::SystemTime,
+};
+
+use anyhow::Result;
+
+/// A scratch directory that only the current test run writes to, removed again when dropped.
+#[derive(Debug)]
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    /// Creates a fresh directory under the system temp directory, named after `prefix`, the
+    /// process and the current time so that concurrent runs never share it.
+    pub fn new(prefix: &str) -> Result<Self> {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)?
+            .as_nanos();
+        let path = std::env::temp_dir().join(format!("{}-{}-{}", prefix, process::id(), nanos));
+        fs::create_dir(&path)?;
+
+        Ok(TempDir(path))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}